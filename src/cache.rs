@@ -1,10 +1,11 @@
 use std::collections::HashMap;
 use std::fs;
 use std::io::{self, Write};
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{bail, Context, Result};
+use log::warn;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -12,6 +13,52 @@ pub struct Entry {
 	// TODO: Simplify timestamp; storing full SystemTime struct/object is inefficient compared to just the (nano)?seconds as integer.
 	timestamp: SystemTime,
 	data: serde_json::Value,
+	#[serde(flatten, default)]
+	validators: Validators,
+}
+
+/// HTTP validators of a cached response, sent back as conditional request headers to check whether the data changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub etag: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub last_modified: Option<String>,
+}
+
+/// Outcome of a conditional request for data that may already be cached.
+pub enum Revalidated<T> {
+	/// The data changed (or nothing was cached), along with the validators of the new response.
+	Modified(T, Validators),
+	/// The cached data is still current.
+	NotModified,
+}
+
+impl Entry {
+	/// Time elapsed since the entry was saved.
+	#[must_use]
+	pub fn age(&self) -> Duration {
+		SystemTime::now()
+			.duration_since(self.timestamp)
+			.unwrap_or_default()
+	}
+
+	/// Size of the entry's data, serialized as JSON, in bytes.
+	#[must_use]
+	pub fn size(&self) -> usize {
+		self.data.to_string().len()
+	}
+
+	#[must_use]
+	pub fn data(&self) -> &serde_json::Value {
+		&self.data
+	}
+
+	/// When the entry was saved (or last found to be current).
+	#[must_use]
+	pub fn timestamp(&self) -> SystemTime {
+		self.timestamp
+	}
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -20,13 +67,15 @@ pub struct Cache {
 	entries: HashMap<String, Entry>,
 	refresh: bool,
 	entry_duration_seconds: u64,
+	#[serde(default)]
+	offline: bool,
 }
 
 impl Cache {
 	/// Initializes a new cache, with timestamped data entries saved to the specified path as JSON.
 	pub fn new(path: PathBuf, refresh: bool, entry_duration_seconds: u64) -> Self {
 		let entries = match fs::read_to_string(&path) {
-			Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+			Ok(contents) => parse_entries(&path, &contents),
 			Err(_) => {
 				fs::create_dir_all(path.parent().unwrap()).unwrap();
 				HashMap::new()
@@ -38,56 +87,367 @@ impl Cache {
 			entries,
 			refresh,
 			entry_duration_seconds,
+			offline: false,
 		}
 	}
 
-	/// Retrieve a keyed value from the cache store, returning `None` if hard refresh is enabled in the cache settings or if the entry's timestamp is older than the specified maximum duration.
+	/// Never access the network, serving every value from the cache store regardless of its age (and failing for values that are not cached).
+	#[must_use]
+	pub fn with_offline(mut self, offline: bool) -> Self {
+		self.offline = offline;
+		self
+	}
+
+	#[must_use]
+	pub fn is_offline(&self) -> bool {
+		self.offline
+	}
+
+	/// Fetch values again from now on, as if `--refresh` was given, e.g. when data must be current.
+	pub fn set_refresh(&mut self, refresh: bool) {
+		self.refresh = refresh;
+	}
+
+	/// Retrieve a keyed value from the cache store, returning `None` if hard refresh is enabled in the cache settings or if the entry's timestamp is older than the specified maximum duration. In offline mode, any cached value is returned.
 	pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
-		if self.refresh {
+		if self.refresh && !self.offline {
 			return None;
 		}
 		self.entries.get(key).and_then(|entry| {
-			let diff = SystemTime::now()
-				.duration_since(entry.timestamp)
-				.unwrap()
-				.as_secs();
-			if diff < self.entry_duration_seconds {
-				serde_json::from_value(entry.data.clone()).ok()
-			} else {
+			if self.is_expired(entry) && !self.offline {
 				None
+			} else {
+				serde_json::from_value(entry.data.clone()).ok()
 			}
 		})
 	}
 
-	/// Wrapper of the [`Cache::get`] function, accepting a closure for retrieving and then saving the value if the value is not present already or invalid.
+	/// Wrapper of the [`Cache::get`] function, accepting a closure for retrieving and then saving the value if the value is not present already or invalid. If retrieving fails but an expired value exists, that value is used instead (with a warning).
+	///
+	/// # Errors
+	///
+	/// Returns an error if the value can't be fetched and no expired value exists, or the cache can't be written.
 	pub fn get_or<T, F>(&mut self, key: &str, fetch: F) -> Result<T>
 	where
 		T: Serialize + DeserializeOwned + Clone,
 		F: FnOnce() -> Result<T>,
+	{
+		self.get_or_revalidate(key, |_| {
+			fetch().map(|value| Revalidated::Modified(value, Validators::default()))
+		})
+	}
+
+	/// Like [`Cache::get_or`], but the closure receives the validators of the expired entry (if any) so that it can make a conditional request. When it reports the data as unmodified, the expired entry is renewed instead of being downloaded again.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the value can't be fetched and no expired value exists, or the cache can't be written.
+	pub fn get_or_revalidate<T, F>(&mut self, key: &str, fetch: F) -> Result<T>
+	where
+		T: Serialize + DeserializeOwned + Clone,
+		F: FnOnce(&Validators) -> Result<Revalidated<T>>,
 	{
 		if let Some(data) = self.get::<T>(key) {
 			return Ok(data);
 		}
-		let value = fetch()?;
-		self.save(key, value)
+		if self.offline {
+			bail!("'{key}' is not cached, so it can't be used while offline (run `purr cache warm` while online first)");
+		}
+
+		let stale = self.entries.get(key).and_then(|entry| {
+			serde_json::from_value::<T>(entry.data.clone())
+				.ok()
+				.map(|data| (data, entry.validators.clone()))
+		});
+		let validators = stale
+			.as_ref()
+			.map(|(_, validators)| validators.clone())
+			.unwrap_or_default();
+
+		match (fetch(&validators), stale) {
+			(Ok(Revalidated::Modified(value, validators)), _) => {
+				self.save_with_validators(key, value, validators)
+			}
+			(Ok(Revalidated::NotModified), Some((data, _))) => {
+				self.renew(key)?;
+				Ok(data)
+			}
+			(Ok(Revalidated::NotModified), None) => {
+				bail!("Received an unmodified response for '{key}', which is not cached")
+			}
+			(Err(err), Some((data, _))) => {
+				warn!("using expired cache entry '{key}', as fetching it again failed: {err}");
+				Ok(data)
+			}
+			(Err(err), None) => Err(err),
+		}
+	}
+
+	/// Retrieve a keyed value from the cache store regardless of its age, e.g. as a fallback when fetching it again failed.
+	#[must_use]
+	pub fn get_stale<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+		self.entries
+			.get(key)
+			.and_then(|entry| serde_json::from_value(entry.data.clone()).ok())
 	}
 
 	/// Save a value under a key to the cache store, returning that same value.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the value can't be serialized or the cache can't be written.
 	pub fn save<T: Serialize>(&mut self, key: &str, value: T) -> Result<T> {
-		self.entries.insert(
-			key.to_string(),
-			Entry {
-				timestamp: SystemTime::now(),
-				data: serde_json::to_value(&value)?,
-			},
-		);
-		self.save_to_file()?;
+		self.save_with_validators(key, value, Validators::default())
+	}
+
+	/// Save many keyed values to the cache store at once, writing the store only once.
+	///
+	/// # Errors
+	///
+	/// Returns an error if a value can't be serialized or the cache can't be written.
+	pub fn save_all<T: Serialize>(
+		&mut self,
+		values: impl IntoIterator<Item = (String, T)>,
+	) -> Result<()> {
+		let timestamp = SystemTime::now();
+		let entries = values
+			.into_iter()
+			.map(|(key, value)| {
+				let entry = Entry {
+					timestamp,
+					data: serde_json::to_value(value)?,
+					validators: Validators::default(),
+				};
+				Ok((key, entry))
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		self.update(|store| store.extend(entries))
+	}
+
+	fn save_with_validators<T: Serialize>(
+		&mut self,
+		key: &str,
+		value: T,
+		validators: Validators,
+	) -> Result<T> {
+		let entry = Entry {
+			timestamp: SystemTime::now(),
+			data: serde_json::to_value(&value)?,
+			validators,
+		};
+		self.update(|entries| {
+			entries.insert(key.to_string(), entry);
+		})?;
 		Ok(value)
 	}
 
-	/// Save the cache to the store path (specified at cache initialization).
+	/// Reset the timestamp of an entry, keeping its data.
+	fn renew(&mut self, key: &str) -> Result<()> {
+		self.update(|entries| {
+			if let Some(entry) = entries.get_mut(key) {
+				entry.timestamp = SystemTime::now();
+			}
+		})
+	}
+
+	/// Path to the cache store.
+	#[must_use]
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Number of seconds entries remain valid for.
+	#[must_use]
+	pub fn entry_duration_seconds(&self) -> u64 {
+		self.entry_duration_seconds
+	}
+
+	/// All entries in the cache store, sorted by key.
+	#[must_use]
+	pub fn entries(&self) -> Vec<(&String, &Entry)> {
+		let mut entries = self.entries.iter().collect::<Vec<_>>();
+		entries.sort_by_key(|(key, _)| *key);
+		entries
+	}
+
+	#[must_use]
+	pub fn entry(&self, key: &str) -> Option<&Entry> {
+		self.entries.get(key)
+	}
+
+	/// Whether an entry is older than the maximum duration and would be fetched again.
+	#[must_use]
+	pub fn is_expired(&self, entry: &Entry) -> bool {
+		entry.age().as_secs() >= self.entry_duration_seconds
+	}
+
+	/// Remove the entry under a key, returning whether it existed.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the cache can't be written.
+	pub fn remove(&mut self, key: &str) -> Result<bool> {
+		let existed = self.entries.contains_key(key);
+		if existed {
+			self.update(|entries| {
+				entries.remove(key);
+			})?;
+		}
+		Ok(existed)
+	}
+
+	/// Remove all entries matching a predicate, returning their keys.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the cache can't be written.
+	pub fn remove_where(
+		&mut self,
+		predicate: impl Fn(&Self, &Entry) -> bool,
+	) -> Result<Vec<String>> {
+		let mut removed = self
+			.entries
+			.iter()
+			.filter(|(_, entry)| predicate(self, entry))
+			.map(|(key, _)| key.clone())
+			.collect::<Vec<_>>();
+		removed.sort();
+
+		if !removed.is_empty() {
+			self.update(|entries| {
+				for key in &removed {
+					entries.remove(key);
+				}
+			})?;
+		}
+		Ok(removed)
+	}
+
+	/// Apply a change to the entries and persist them to the store path (specified at cache initialization).
+	///
+	/// Other purr processes may have written to the store since it was loaded, so while holding an exclusive lock the store is read again, the change is applied on top of it and the result is written to a temporary file that then replaces the store. Readers therefore never see a partially written store, and concurrent writers don't lose each other's entries.
+	fn update(&mut self, change: impl FnOnce(&mut HashMap<String, Entry>)) -> Result<()> {
+		let lock = fs::File::create(self.path.with_extension("json.lock"))
+			.context("Failed to create cache lock file")?;
+		lock.lock().context("Failed to lock cache store")?;
+
+		// Everything this process changed was already written, so the store on disk is the latest state.
+		self.entries = fs::read_to_string(&self.path)
+			.map(|contents| parse_entries(&self.path, &contents))
+			.unwrap_or_default();
+		change(&mut self.entries);
+
+		let result = self
+			.save_to_file()
+			.with_context(|| format!("Failed to write cache store {}", self.path.display()));
+		lock.unlock().context("Failed to unlock cache store")?;
+		result
+	}
+
+	/// Write the entries to a temporary file next to the store, then rename it over the store.
 	fn save_to_file(&self) -> io::Result<()> {
-		let mut file = fs::File::create(&self.path)?;
-		file.write_all(serde_json::to_string(&self.entries)?.as_bytes())
+		let temporary = self
+			.path
+			.with_extension(format!("json.{}.tmp", std::process::id()));
+		let mut file = fs::File::create(&temporary)?;
+		file.write_all(serde_json::to_string(&self.entries)?.as_bytes())?;
+		file.sync_all()?;
+		fs::rename(&temporary, &self.path).inspect_err(|_| {
+			let _ = fs::remove_file(&temporary);
+		})
+	}
+}
+
+/// Parse the contents of a cache store, warning (rather than silently resetting) when it is invalid.
+fn parse_entries(path: &Path, contents: &str) -> HashMap<String, Entry> {
+	serde_json::from_str(contents).unwrap_or_else(|err| {
+		warn!(
+			"ignoring invalid cache store {} ({err}); it will be replaced",
+			path.display()
+		);
+		HashMap::new()
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use color_eyre::eyre::eyre;
+
+	use super::{Cache, Revalidated, Validators};
+
+	#[test]
+	fn merge_concurrent_writes() {
+		let path = std::env::temp_dir().join("purr-tests/concurrent/store.json");
+		let _ = fs::remove_file(&path);
+		let load = || Cache::new(path.clone(), false, 60);
+
+		let mut first = load();
+		let mut second = load();
+		first.save("first", 1).unwrap();
+		second.save("second", 2).unwrap();
+		assert_eq!(load().get::<i32>("first"), Some(1));
+		assert_eq!(load().get::<i32>("second"), Some(2));
+
+		// Entries removed by another process are not brought back.
+		second.remove("first").unwrap();
+		first.save("third", 3).unwrap();
+		assert_eq!(load().get::<i32>("first"), None);
+
+		fs::write(&path, "{ not json").unwrap();
+		let mut cache = load();
+		assert_eq!(cache.entries().len(), 0);
+		cache.save("fourth", 4).unwrap();
+		assert_eq!(load().get::<i32>("fourth"), Some(4));
+	}
+
+	#[test]
+	fn revalidate_expired_entries() {
+		let path = std::env::temp_dir().join("purr-tests/revalidate/store.json");
+		let _ = fs::remove_file(&path);
+		let validators = Validators {
+			etag: Some("\"abc\"".to_string()),
+			last_modified: None,
+		};
+
+		// Every entry is expired immediately.
+		let mut cache = Cache::new(path.clone(), false, 0);
+		let value = cache
+			.get_or_revalidate("data", |_| Ok(Revalidated::Modified(1, validators.clone())))
+			.unwrap();
+		assert_eq!(value, 1);
+
+		let value = cache
+			.get_or_revalidate::<i32, _>("data", |sent| {
+				assert_eq!(sent, &validators);
+				Ok(Revalidated::NotModified)
+			})
+			.unwrap();
+		assert_eq!(value, 1);
+
+		let value = cache
+			.get_or::<i32, _>("data", || Err(eyre!("offline")))
+			.unwrap();
+		assert_eq!(value, 1);
+		assert!(cache
+			.get_or::<i32, _>("missing", || Err(eyre!("offline")))
+			.is_err());
+	}
+
+	#[test]
+	fn serve_everything_from_cache_offline() {
+		let path = std::env::temp_dir().join("purr-tests/offline/store.json");
+		let _ = fs::remove_file(&path);
+		Cache::new(path.clone(), false, 0).save("data", 1).unwrap();
+
+		let mut cache = Cache::new(path, true, 0).with_offline(true);
+		assert_eq!(cache.get::<i32>("data"), Some(1));
+		assert!(cache
+			.get_or::<i32, _>("missing", || panic!("fetched while offline"))
+			.unwrap_err()
+			.to_string()
+			.contains("'missing' is not cached"));
 	}
 }
@@ -1,10 +1,12 @@
 use std::path::PathBuf;
 
-use clap::{arg, ArgAction, Args, Parser, Subcommand, ValueEnum};
-use color_eyre::owo_colors::OwoColorize;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
 use url::Url;
 
+use crate::cmd::query::filter::Filter;
 use crate::models::categories::CATEGORIES;
+use crate::sources::Source;
+use crate::utils::{did_you_mean, suggest};
 
 #[derive(Parser)]
 #[command(name = "purr", version, arg_required_else_help(true))]
@@ -15,6 +17,38 @@ pub struct Cli {
 	/// Hard refresh cached data
 	#[arg(short, long, global = true)]
 	pub refresh: bool,
+
+	/// Never access the network, using cached data regardless of its age
+	#[arg(long, env = "PURR_OFFLINE", value_parser = clap::builder::BoolishValueParser::new(), global = true)]
+	pub offline: bool,
+
+	/// How long cached data stays valid for (e.g. 30m, 12h, 7d; plain numbers are seconds)
+	#[arg(long, env = "PURR_TTL", value_parser = valid_duration, global = true)]
+	pub ttl: Option<u64>,
+
+	/// Path to the configuration file
+	#[arg(long, env = "PURR_CONFIG", global = true)]
+	pub config: Option<PathBuf>,
+
+	/// URL or file path to read ports.yml from
+	#[arg(long, env = "PURR_PORTS_SOURCE", global = true)]
+	pub ports_source: Option<Source>,
+
+	/// URL or file path to read userstyles.yml from
+	#[arg(long, env = "PURR_USERSTYLES_SOURCE", global = true)]
+	pub userstyles_source: Option<Source>,
+
+	/// Base URL of the GitHub REST API, e.g. for GitHub Enterprise
+	#[arg(long, env = "PURR_GITHUB_API", value_parser = valid_url, global = true)]
+	pub github_api: Option<String>,
+
+	/// URL of the GitHub GraphQL API (derived from the REST API URL by default)
+	#[arg(long, env = "PURR_GITHUB_GRAPHQL", value_parser = valid_url, global = true)]
+	pub github_graphql: Option<String>,
+
+	/// GitHub organization to query
+	#[arg(long, env = "PURR_ORGANIZATION", global = true)]
+	pub organization: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -36,17 +70,8 @@ pub enum Commands {
 		#[arg(short, long, value_enum, default_value = "identifier")]
 		get: Vec<Key>,
 
-		// See https://jwodder.github.io/kbits/posts/clap-bool-negate/.
-		// Cursed code to enable the correct relationship between `--userstyles` and `--no-userstyles`.
-		/// Include userstyles in query results
-		#[clap(long = "userstyles", overrides_with = "userstyles", global = true)]
-		_no_userstyles: bool,
-		/// Exclude userstyles in query results
-		#[clap(long = "no-userstyles", action = ArgAction::SetFalse, global = true)]
-		userstyles: bool,
-		/// Exclude non-userstyles from query results
-		#[arg(long, conflicts_with_all = ["userstyles", "_no_userstyles"], global = true)]
-		only_userstyles: bool,
+		#[command(flatten)]
+		options: Box<QueryOptions>,
 	},
 	/// Initialize a new port or userstyle
 	Init {
@@ -59,6 +84,93 @@ pub enum Commands {
 
 		#[arg(short, long)]
 		output: Option<PathBuf>,
+
+		/// List the substitutions that would be made without writing anything
+		#[arg(long)]
+		dry_run: bool,
+	},
+	/// Work with Whiskers, Catppuccin's port creation tool
+	Whiskers {
+		#[command(subcommand)]
+		command: WhiskersCommand,
+
+		/// Output format of the results
+		#[arg(long, value_enum, default_value_t, global = true)]
+		format: Format,
+	},
+	/// Inspect and manage cached data
+	Cache {
+		#[command(subcommand)]
+		command: CacheCommand,
+
+		/// Output format of the results
+		#[arg(long, value_enum, default_value_t, global = true)]
+		format: Format,
+	},
+	/// Check the data sources against the GitHub organization
+	Audit {
+		#[command(subcommand)]
+		command: AuditCommand,
+
+		/// Output format of the results
+		#[arg(long, value_enum, default_value_t, global = true)]
+		format: Format,
+	},
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommand {
+	/// Find ports without a repository, repositories without a port and ports with archived repositories
+	Repos {
+		/// Additional repositories that aren't expected to be ports
+		#[arg(long, value_delimiter = ',', name = "REPOSITORY")]
+		allow: Vec<String>,
+
+		/// GitHub token, otherwise found in the environment, the GitHub CLI or git credentials
+		#[arg(long)]
+		token: Option<String>,
+	},
+}
+
+#[derive(Subcommand)]
+pub enum WhiskersCommand {
+	/// Detect whether a port uses Whiskers from its files, regardless of its custom property
+	Detect {
+		/// Path to a local checkout of the port
+		#[arg(conflicts_with = "repo")]
+		path: Option<PathBuf>,
+
+		/// Repositories of the organization to inspect on GitHub instead
+		#[arg(long, value_delimiter = ',')]
+		repo: Vec<String>,
+
+		/// Inspect every non-archived repository of the organization on GitHub
+		#[arg(long, conflicts_with_all = ["path", "repo"])]
+		all: bool,
+
+		/// GitHub token, otherwise found in the environment, the GitHub CLI or git credentials
+		#[arg(long)]
+		token: Option<String>,
+	},
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommand {
+	/// List cached entries with their age, size and expiry
+	List,
+	/// Show the data cached under a key
+	Show { key: String },
+	/// Remove a cached entry, or all entries if no key is given
+	Clear { key: Option<String> },
+	/// Print the path to the cache store
+	Path,
+	/// Remove expired entries
+	Prune,
+	/// Fetch and cache everything needed to use purr offline
+	Warm {
+		/// GitHub token, needed to cache repository data and Whiskers statuses (otherwise found in the environment, the GitHub CLI or git credentials)
+		#[arg(long)]
+		token: Option<String>,
 	},
 }
 
@@ -115,69 +227,168 @@ pub enum Query {
 		#[command(flatten)]
 		options: ExtraOptions<Key>,
 	},
+	/// Query what a user maintains and used to maintain
+	Maintainer {
+		/// GitHub login of the user
+		login: String,
+
+		/// GitHub token, to include the user's open pull requests across the organization (otherwise found in the environment, the GitHub CLI or git credentials)
+		#[arg(long)]
+		token: Option<String>,
+	},
+	/// Query ports and userstyles without any current maintainers
+	Orphaned(OrphanedQuery),
 	/// Query about the Whiskers migration
-	Whiskers {
-		/// Name of the repository to query
-		#[arg(long, name = "REPOSITORY", conflicts_with_all = ["count"])]
-		r#for: Option<String>,
+	Whiskers(WhiskersQuery),
+	/// Query the values of a custom property of the organization's repositories
+	Property(PropertyQuery),
+	/// Query star counts of the whole organization or per-repository
+	Stars(StarsQuery),
+	/// Query aggregate statistics about ports
+	Stats {
+		/// Break statistics down by a specific dimension
+		#[arg(long, value_enum)]
+		by: Option<StatsDimension>,
+	},
+	/// Query ports with matching fields
+	Has(HasQuery),
+}
 
-		/// Whiskers state to check for
-		#[arg(short, long, name = "STATE")]
-		is: Option<WhiskersCustomProperty>,
+#[derive(Args)]
+pub struct OrphanedQuery {
+	/// Include the last push date and open issue and pull request counts of each port's repository
+	#[arg(long)]
+	pub activity: bool,
+
+	/// Sort by staleness, least recently pushed to first
+	#[arg(long, requires = "activity")]
+	pub stalest: bool,
+
+	/// GitHub token, otherwise found in the environment, the GitHub CLI or git credentials
+	#[arg(long)]
+	pub token: Option<String>,
+}
 
-		/// Invert matched results
-		#[arg(short, long)]
-		not: bool,
+#[derive(Args)]
+pub struct WhiskersQuery {
+	/// Name of the repository to query
+	#[arg(long, name = "REPOSITORY", conflicts_with_all = ["count"])]
+	pub r#for: Option<String>,
 
-		/// Count the number of results
-		#[arg(short, long)]
-		count: bool,
+	/// Whiskers state to check for
+	#[arg(short, long, name = "STATE")]
+	pub is: Option<WhiskersCustomProperty>,
 
-		#[arg(long, env = "GITHUB_TOKEN")]
-		token: String,
-	},
-	/// Query star counts of the whole organization or per-repository
-	Stars {
-		/// Name of the repository to query
-		#[arg(long, name = "REPOSITORY", conflicts_with = "archived")]
-		r#for: Option<String>,
+	/// Invert matched results
+	#[arg(short, long)]
+	pub not: bool,
 
-		/// Whether to include archived repositories in the total count
-		#[arg(long, action = ArgAction::Set, num_args = 0..=1, default_value = "false", default_missing_value = "true")]
-		archived: bool,
+	/// Count the number of results
+	#[arg(short, long)]
+	pub count: bool,
 
-		#[arg(long, env = "GITHUB_TOKEN")]
-		token: String,
-	},
-	/// Query ports with matching fields
-	Has {
-		#[arg(long)]
-		name: Option<String>,
+	/// Check that migrated repositories have a .tera template on their default branch (one request per repository)
+	#[arg(long, conflicts_with_all = ["REPOSITORY", "STATE"])]
+	pub check_templates: bool,
 
-		#[arg(long = "category", value_delimiter = ',', value_parser = valid_category)]
-		categories: Option<Vec<String>>,
+	/// GitHub token, otherwise found in the environment, the GitHub CLI or git credentials
+	#[arg(long)]
+	pub token: Option<String>,
+}
 
-		#[arg(long, num_args = 0..=1, default_missing_value = "true")]
-		upstreamed: Option<bool>,
+#[derive(Args)]
+pub struct PropertyQuery {
+	/// Name of the custom property, e.g. whiskers
+	pub name: String,
 
-		#[arg(long)]
-		platform: Option<Vec<String>>,
+	/// Name of the repository to query
+	#[arg(long, name = "REPOSITORY", conflicts_with_all = ["count"])]
+	pub r#for: Option<String>,
 
-		#[arg(long, num_args = 0..=1, default_missing_value = "true")]
-		icon: Option<String>,
+	/// Value to check for ("unset" matches repositories without the property)
+	#[arg(short, long, name = "VALUE")]
+	pub is: Option<String>,
 
-		#[arg(long)]
-		color: Option<String>,
+	/// Invert matched results
+	#[arg(short, long, requires = "VALUE")]
+	pub not: bool,
 
-		#[arg(long, num_args = 0..=1, default_missing_value = "true")]
-		alias: Option<String>,
+	/// Count the number of results
+	#[arg(short, long)]
+	pub count: bool,
 
-		#[arg(long, num_args = 0..=1, default_missing_value = "true", value_parser = valid_url)]
-		url: Option<String>,
+	/// GitHub token, otherwise found in the environment, the GitHub CLI or git credentials
+	#[arg(long)]
+	pub token: Option<String>,
+}
 
-		#[command(flatten)]
-		options: ExtraOptions<Key>,
-	},
+#[derive(Args)]
+pub struct StarsQuery {
+	/// Name of the repository to query
+	#[arg(long, name = "REPOSITORY", conflicts_with = "archived")]
+	pub r#for: Option<String>,
+
+	/// Whether to include archived repositories alongside the non-archived ones
+	#[arg(long, action = ArgAction::Set, num_args = 0..=1, default_value = "false", default_missing_value = "true")]
+	pub archived: bool,
+
+	/// Only count repositories of ports in all of the given categories
+	#[arg(long = "category", value_delimiter = ',', value_parser = valid_category, conflicts_with_all = ["REPOSITORY", "snapshot"])]
+	pub categories: Option<Vec<String>>,
+
+	/// Only count repositories of ports for all of the given platforms
+	#[arg(long, value_delimiter = ',', conflicts_with_all = ["REPOSITORY", "snapshot"])]
+	pub platform: Option<Vec<String>>,
+
+	/// List the repositories ranked by their star count
+	#[arg(long, conflicts_with_all = ["REPOSITORY", "snapshot", "since"])]
+	pub list: bool,
+
+	/// List only the given number of repositories with the most stars
+	#[arg(long, value_name = "N", conflicts_with_all = ["REPOSITORY", "snapshot", "since"])]
+	pub top: Option<usize>,
+
+	/// Record the star count of each repository in the star history
+	#[arg(long, conflicts_with_all = ["REPOSITORY", "since"])]
+	pub snapshot: bool,
+
+	/// Report star changes since a date (YYYY-MM-DD), based on the star history
+	#[arg(long, value_parser = valid_date, conflicts_with = "REPOSITORY")]
+	pub since: Option<String>,
+
+	/// GitHub token, otherwise found in the environment, the GitHub CLI or git credentials
+	#[arg(long)]
+	pub token: Option<String>,
+}
+
+#[derive(Args)]
+pub struct HasQuery {
+	#[arg(long)]
+	pub name: Option<String>,
+
+	#[arg(long = "category", value_delimiter = ',', value_parser = valid_category)]
+	pub categories: Option<Vec<String>>,
+
+	#[arg(long, num_args = 0..=1, default_missing_value = "true")]
+	pub upstreamed: Option<bool>,
+
+	#[arg(long)]
+	pub platform: Option<Vec<String>>,
+
+	#[arg(long, num_args = 0..=1, default_missing_value = "true")]
+	pub icon: Option<String>,
+
+	#[arg(long)]
+	pub color: Option<String>,
+
+	#[arg(long, num_args = 0..=1, default_missing_value = "true")]
+	pub alias: Option<String>,
+
+	#[arg(long, num_args = 0..=1, default_missing_value = "true", value_parser = valid_url)]
+	pub url: Option<String>,
+
+	#[command(flatten)]
+	pub options: ExtraOptions<Key>,
 }
 
 #[derive(Subcommand)]
@@ -212,6 +423,55 @@ pub enum UserstylesQuery {
 	},
 }
 
+/// Options shared by the `query` command and all of its subcommands.
+#[derive(Args)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct QueryOptions {
+	// See https://jwodder.github.io/kbits/posts/clap-bool-negate/.
+	// Cursed code to enable the correct relationship between `--userstyles` and `--no-userstyles`.
+	/// Include userstyles in query results
+	#[clap(long = "userstyles", overrides_with = "userstyles", global = true)]
+	_no_userstyles: bool,
+	/// Exclude userstyles in query results
+	#[clap(long = "no-userstyles", action = ArgAction::SetFalse, global = true)]
+	pub userstyles: bool,
+	/// Exclude non-userstyles from query results
+	#[arg(long, conflicts_with_all = ["userstyles", "_no_userstyles"], global = true)]
+	pub only_userstyles: bool,
+
+	/// Only include results matching a filter expression (e.g. "categories contains cli AND NOT upstreamed")
+	#[arg(long = "where", name = "EXPR", global = true)]
+	pub filter: Option<Filter>,
+
+	/// Return the closest matches for `--for` and `has --name` instead of requiring an exact name
+	#[arg(long, global = true)]
+	pub fuzzy: bool,
+
+	/// Output format of the results
+	#[arg(long, value_enum, default_value_t, global = true)]
+	pub format: Format,
+
+	/// Sort results by a specific property
+	#[arg(long, value_enum, default_value = "identifier", global = true)]
+	pub sort_by: Key,
+
+	/// Sort results in descending order
+	#[arg(long, global = true)]
+	pub desc: bool,
+
+	/// Limit the number of results
+	#[arg(long, global = true)]
+	pub limit: Option<usize>,
+
+	/// Group results by a specific property, listing results under each of its values
+	#[arg(long, value_enum, global = true)]
+	pub group_by: Option<Key>,
+
+	/// GitHub token, needed for repository properties such as description or topics (otherwise found in the environment, the GitHub CLI or git credentials)
+	#[arg(long)]
+	pub token: Option<String>,
+}
+
 #[derive(Args)]
 pub struct ExtraOptions<K: Send + Sync + Default + ValueEnum + 'static> {
 	/// Invert matched results
@@ -228,9 +488,21 @@ pub struct ExtraOptions<K: Send + Sync + Default + ValueEnum + 'static> {
 }
 
 #[derive(
-	Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default, Hash, serde::Serialize,
+	Debug,
+	Copy,
+	Clone,
+	PartialEq,
+	Eq,
+	PartialOrd,
+	Ord,
+	ValueEnum,
+	Default,
+	Hash,
+	serde::Serialize,
+	strum::Display,
 )]
 #[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
 pub enum Key {
 	#[default]
 	Identifier,
@@ -244,6 +516,43 @@ pub enum Key {
 	Url,
 	CurrentMaintainers,
 	PastMaintainers,
+	/// Repository description (requires a GitHub token)
+	Description,
+	/// Repository topics (requires a GitHub token)
+	Topics,
+	/// Primary language of the repository (requires a GitHub token)
+	Language,
+	/// When the repository was last pushed to (requires a GitHub token)
+	PushedAt,
+	/// Number of open issues (requires a GitHub token)
+	OpenIssues,
+	/// Number of open pull requests (requires a GitHub token)
+	OpenPullRequests,
+	/// Default branch of the repository (requires a GitHub token)
+	DefaultBranch,
+	/// SPDX identifier of the repository's license (requires a GitHub token)
+	License,
+	/// Homepage URL of the repository (requires a GitHub token)
+	Homepage,
+}
+
+impl Key {
+	/// Whether the property comes from the port's repository on GitHub rather than ports.yml.
+	#[must_use]
+	pub fn is_repository(self) -> bool {
+		matches!(
+			self,
+			Key::Description
+				| Key::Topics
+				| Key::Language
+				| Key::PushedAt
+				| Key::OpenIssues
+				| Key::OpenPullRequests
+				| Key::DefaultBranch
+				| Key::License
+				| Key::Homepage
+		)
+	}
 }
 
 #[derive(
@@ -262,6 +571,31 @@ pub enum UserstyleKey {
 	PastMaintainers,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Default)]
+pub enum Format {
+	/// Pretty-printed JSON
+	#[default]
+	Json,
+	/// Newline-delimited JSON, one result per line
+	Ndjson,
+	/// YAML, keyed by identifier like ports.yml
+	Yaml,
+	/// Aligned columns for reading in a terminal
+	Table,
+	/// Comma-separated values
+	Csv,
+	/// Tab-separated values
+	Tsv,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum StatsDimension {
+	Category,
+	Platform,
+	Maintainer,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, strum::Display)]
 #[strum(serialize_all = "snake_case")]
 pub enum WhiskersCustomProperty {
@@ -278,28 +612,80 @@ fn valid_url(url: &str) -> Result<String, String> {
 	}
 }
 
+fn valid_date(date: &str) -> Result<String, String> {
+	let parts = date
+		.split('-')
+		.map(|part| {
+			part.parse::<u32>()
+				.ok()
+				.filter(|_| part.chars().all(|c| c.is_ascii_digit()))
+		})
+		.collect::<Vec<_>>();
+	match (date.len(), parts.as_slice()) {
+		(10, [Some(_), Some(1..=12), Some(1..=31)]) => Ok(date.to_string()),
+		_ => Err(format!("{date} is not a valid date, expected YYYY-MM-DD")),
+	}
+}
+
+fn valid_duration(duration: &str) -> Result<u64, String> {
+	let (number, unit) = duration.split_at(
+		duration
+			.find(|c: char| !c.is_ascii_digit())
+			.unwrap_or(duration.len()),
+	);
+	let multiplier = match unit {
+		"" | "s" => 1,
+		"m" => 60,
+		"h" => 60 * 60,
+		"d" => 24 * 60 * 60,
+		"w" => 7 * 24 * 60 * 60,
+		_ => {
+			return Err(format!(
+				"unknown unit '{unit}', expected one of s, m, h, d or w"
+			))
+		}
+	};
+
+	number
+		.parse::<u64>()
+		.ok()
+		.and_then(|n| n.checked_mul(multiplier))
+		.ok_or_else(|| format!("{duration} is not a valid duration"))
+}
+
 fn valid_category(c: &str) -> Result<String, String> {
 	if CATEGORIES.contains(&c) {
 		Ok(String::from(c))
 	} else {
-		use std::cmp::Ordering;
-
-		let mut distances = CATEGORIES
+		let best = suggest(c, CATEGORIES)
+			.first()
+			.map(|(category, _)| *category)
 			.into_iter()
-			.map(|category: &str| (category, strsim::jaro(c, category)))
-			.filter(|(_, confidence)| *confidence > 0.7)
 			.collect::<Vec<_>>();
 
-		distances.sort_unstable_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
-		let best = distances.first().map(|a| a.0.to_owned());
-
-		Err(format!(
-			"not a valid category{}",
-			if let Some(best) = best {
-				format!(". Did you mean '{}'?", best.green())
-			} else {
-				String::new()
-			}
-		))
+		Err(format!("not a valid category{}", did_you_mean(&best)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{valid_date, valid_duration};
+
+	#[test]
+	fn parse_durations() {
+		assert_eq!(valid_duration("90"), Ok(90));
+		assert_eq!(valid_duration("30m"), Ok(30 * 60));
+		assert_eq!(valid_duration("7d"), Ok(7 * 24 * 60 * 60));
+		assert!(valid_duration("3x").is_err());
+		assert!(valid_duration("h").is_err());
+		assert!(valid_duration("99999999999999999w").is_err());
+	}
+
+	#[test]
+	fn parse_dates() {
+		assert_eq!(valid_date("2024-02-29"), Ok("2024-02-29".to_string()));
+		assert!(valid_date("2024-13-01").is_err());
+		assert!(valid_date("2024-1-01").is_err());
+		assert!(valid_date("yesterday").is_err());
 	}
 }
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use color_eyre::eyre::Result;
+use serde::Serialize;
+
+use crate::cache::Cache;
+use crate::cli::{AuditCommand, Format};
+use crate::format::display;
+use crate::github::{fetch_all_repositories, Repository};
+use crate::models::ports;
+use crate::sources::Sources;
+use crate::token;
+
+/// Exit code when an audit finds anything, distinct from errors and the codes of [`crate::github::GithubError`].
+pub const FINDINGS_EXIT_CODE: u8 = 8;
+
+/// Repositories in the organization that aren't ports.
+pub const META_REPOSITORIES: &[&str] = &[
+	".github",
+	"catppuccin",
+	"catwalk",
+	"community",
+	"template",
+	"toolbox",
+	"userstyles",
+	"website",
+	"whiskers",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Problem {
+	/// A port in ports.yml without a repository in the organization.
+	MissingRepository,
+	/// A repository in the organization that isn't in ports.yml.
+	MissingPort,
+	/// A port whose repository is archived.
+	ArchivedRepository,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Finding {
+	pub problem: Problem,
+	pub repository: String,
+}
+
+/// Run an audit, printing its findings. Returns whether anything was found.
+///
+/// # Errors
+///
+/// Returns an error if the data needed for the audit can't be loaded or fetched.
+pub fn handle(
+	cache: &mut Cache,
+	sources: &Sources,
+	command: AuditCommand,
+	format: Format,
+) -> Result<bool> {
+	match command {
+		AuditCommand::Repos { allow, token } => {
+			let ports = sources.ports(cache)?;
+			let repositories = fetch_all_repositories(
+				cache,
+				&sources.github,
+				&token::require(token, &sources.github, cache)?,
+			)?
+			.into_iter()
+			.flatten()
+			.collect::<Vec<_>>();
+
+			let findings = repos(&ports, &repositories, &allow);
+			let found = !findings.is_empty();
+			display(findings, false, format)?;
+			Ok(found)
+		}
+	}
+}
+
+/// Join the ports with the organization's repositories (by identifier). Ports hosted outside of the organization (with a `url`) are not expected to have a repository, and neither the [`META_REPOSITORIES`] nor the allowed repositories are expected to be ports.
+#[must_use]
+pub fn repos(ports: &ports::Root, repositories: &[Repository], allow: &[String]) -> Vec<Finding> {
+	let repositories = repositories
+		.iter()
+		.map(|repository| (repository.name.as_str(), repository))
+		.collect::<HashMap<_, _>>();
+	let finding = |problem, repository: &str| Finding {
+		problem,
+		repository: repository.to_string(),
+	};
+
+	let mut findings = vec![];
+	for (identifier, port) in &ports.ports {
+		match repositories.get(identifier.as_str()) {
+			None if port.url.is_none() => {
+				findings.push(finding(Problem::MissingRepository, identifier));
+			}
+			Some(repository) if repository.is_archived => {
+				findings.push(finding(Problem::ArchivedRepository, identifier));
+			}
+			_ => {}
+		}
+	}
+	for (name, repository) in repositories {
+		let allowed = META_REPOSITORIES.contains(&name) || allow.iter().any(|a| a == name);
+		if !allowed && !repository.is_archived && !ports.ports.contains_key(name) {
+			findings.push(finding(Problem::MissingPort, name));
+		}
+	}
+
+	findings.sort();
+	findings
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{repos, Finding, Problem};
+	use crate::github::Repository;
+	use crate::models::ports::{Port, Root};
+
+	fn repository(name: &str, is_archived: bool) -> Repository {
+		Repository {
+			name: name.to_string(),
+			is_archived,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn find_mismatches() {
+		let port = |url: Option<&str>| Port {
+			url: url.map(ToString::to_string),
+			..Default::default()
+		};
+		let ports = Root {
+			collaborators: vec![],
+			ports: [
+				("nvim".to_string(), port(None)),
+				("bat".to_string(), port(None)),
+				("kitty".to_string(), port(None)),
+				(
+					"purr".to_string(),
+					port(Some("https://github.com/uncenter/purr")),
+				),
+			]
+			.into(),
+		};
+		let repositories = vec![
+			repository("nvim", false),
+			repository("kitty", true),
+			repository("helix", false),
+			repository("old-port", true),
+			repository("whiskers", false),
+			repository("discord-bot", false),
+		];
+
+		let finding = |problem, repository: &str| Finding {
+			problem,
+			repository: repository.to_string(),
+		};
+		assert_eq!(
+			repos(&ports, &repositories, &["discord-bot".to_string()]),
+			vec![
+				finding(Problem::MissingRepository, "bat"),
+				finding(Problem::MissingPort, "helix"),
+				finding(Problem::ArchivedRepository, "kitty"),
+			]
+		);
+	}
+}
@@ -0,0 +1,187 @@
+use color_eyre::eyre::{bail, Result};
+use log::warn;
+use serde::Serialize;
+
+use crate::cache::Cache;
+use crate::cli::{CacheCommand, Format};
+use crate::cmd::init::{self, PORT_TEMPLATES};
+use crate::format::{display, display_value};
+use crate::github;
+use crate::sources::Sources;
+use crate::token;
+use crate::utils::{did_you_mean, suggest};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct EntryInfo {
+	key: String,
+	age: String,
+	size: String,
+	expires: String,
+}
+
+/// Inspect or clear the cache.
+///
+/// # Errors
+///
+/// Returns an error if the cache can't be read or written.
+pub fn handle(
+	cache: &mut Cache,
+	sources: &Sources,
+	command: CacheCommand,
+	format: Format,
+) -> Result<()> {
+	match command {
+		CacheCommand::List => {
+			let ttl = cache.entry_duration_seconds();
+			let entries = cache
+				.entries()
+				.into_iter()
+				.map(|(key, entry)| {
+					let age = entry.age().as_secs();
+					EntryInfo {
+						key: key.clone(),
+						age: format_duration(age),
+						size: format_size(entry.size()),
+						expires: if cache.is_expired(entry) {
+							"expired".to_string()
+						} else {
+							format!("in {}", format_duration(ttl - age))
+						},
+					}
+				})
+				.collect::<Vec<_>>();
+
+			display(entries, false, format)?;
+		}
+		CacheCommand::Show { key } => {
+			let Some(entry) = cache.entry(&key) else {
+				bail!(
+					"no cache entry with the key '{key}'{}",
+					suggest_keys(cache, &key)
+				);
+			};
+			display_value(entry.data(), format)?;
+		}
+		CacheCommand::Clear { key: Some(key) } => {
+			if !cache.remove(&key)? {
+				bail!(
+					"no cache entry with the key '{key}'{}",
+					suggest_keys(cache, &key)
+				);
+			}
+			println!("Removed '{key}'");
+		}
+		CacheCommand::Clear { key: None } => {
+			let removed = cache.remove_where(|_, _| true)?;
+			println!("Removed {} entries", removed.len());
+		}
+		CacheCommand::Path => println!("{}", cache.path().display()),
+		CacheCommand::Prune => {
+			let removed = cache.remove_where(Cache::is_expired)?;
+			println!("Removed {} expired entries", removed.len());
+		}
+		CacheCommand::Warm { token } => warm(cache, sources, token)?,
+	}
+
+	Ok(())
+}
+
+/// Fetch everything that `query`, `init` and the Whiskers queries need, so that they work with `--offline`.
+fn warm(cache: &mut Cache, sources: &Sources, token: Option<String>) -> Result<()> {
+	if cache.is_offline() {
+		bail!("can't warm the cache while offline");
+	}
+
+	sources.ports(cache)?;
+	println!("Loaded ports from {}", sources.ports);
+	sources.userstyles(cache)?;
+	println!("Loaded userstyles from {}", sources.userstyles);
+
+	init::userstyle_template(cache)?;
+	for repository in PORT_TEMPLATES {
+		github::fetch_tarball(cache, repository)?;
+	}
+	println!("Cached port and userstyle templates");
+
+	let Some(token) = token::resolve(token, &sources.github) else {
+		warn!("no GitHub token found, so repository data and Whiskers statuses were not cached");
+		return Ok(());
+	};
+
+	let repositories = github::fetch_all_repositories(cache, &sources.github, &token)?;
+	println!("Cached {} repositories", repositories.len());
+	let names = repositories
+		.iter()
+		.flatten()
+		.filter(|r| !r.is_archived)
+		.map(|r| r.name.as_str())
+		.collect::<Vec<_>>();
+	github::fetch_properties(cache, &sources.github, &names, "whiskers", &token)?;
+	println!("Cached Whiskers statuses");
+
+	Ok(())
+}
+
+fn suggest_keys(cache: &Cache, key: &str) -> String {
+	did_you_mean(
+		&suggest(
+			key,
+			cache.entries().into_iter().map(|(key, _)| key.as_str()),
+		)
+		.into_iter()
+		.take(3)
+		.map(|(key, _)| key)
+		.collect::<Vec<_>>(),
+	)
+}
+
+/// Format a number of seconds using its two largest units, e.g. `3h 12m`.
+fn format_duration(seconds: u64) -> String {
+	let units = [
+		("d", seconds / 86400),
+		("h", seconds / 3600 % 24),
+		("m", seconds / 60 % 60),
+		("s", seconds % 60),
+	];
+
+	let parts = units
+		.iter()
+		.skip_while(|(_, value)| *value == 0)
+		.take(2)
+		.filter(|(_, value)| *value != 0)
+		.map(|(unit, value)| format!("{value}{unit}"))
+		.collect::<Vec<_>>();
+
+	if parts.is_empty() {
+		"0s".to_string()
+	} else {
+		parts.join(" ")
+	}
+}
+
+fn format_size(bytes: usize) -> String {
+	#[allow(clippy::cast_precision_loss)]
+	let bytes = bytes as f64;
+	if bytes < 1024.0 {
+		format!("{bytes} B")
+	} else if bytes < 1024.0 * 1024.0 {
+		format!("{:.1} KiB", bytes / 1024.0)
+	} else {
+		format!("{:.1} MiB", bytes / 1024.0 / 1024.0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{format_duration, format_size};
+
+	#[test]
+	fn humanize_values() {
+		assert_eq!(format_duration(0), "0s");
+		assert_eq!(format_duration(59), "59s");
+		assert_eq!(format_duration(3 * 3600 + 12 * 60 + 5), "3h 12m");
+		assert_eq!(format_duration(86400 + 30), "1d");
+		assert_eq!(format_size(512), "512 B");
+		assert_eq!(format_size(2048), "2.0 KiB");
+	}
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::process::Command;
-use std::{env, fs, io};
+use std::{env, fs};
 
 use color_eyre::eyre::{bail, Result};
 use fancy_regex::Regex;
@@ -17,7 +17,15 @@ use crate::models::categories::CATEGORIES;
 use crate::models::userstyles::{Userstyle, UserstylesRoot};
 use crate::utils::fetch_text;
 
-pub fn port(name: Option<String>, url: Option<String>, whiskers: Option<bool>) -> Result<()> {
+/// Repositories that new ports are created from, with and without Whiskers.
+pub const PORT_TEMPLATES: [&str; 2] = ["uncenter/ctp-template-whiskers", "catppuccin/template"];
+
+pub fn port(
+	cache: &mut Cache,
+	name: Option<String>,
+	url: Option<String>,
+	whiskers: Option<bool>,
+) -> Result<()> {
 	let name = name.unwrap_or_else(|| {
 		Text::new("What is the name of this port?")
 			.prompt()
@@ -44,22 +52,16 @@ pub fn port(name: Option<String>, url: Option<String>, whiskers: Option<bool>) -
 	if target.exists() {
 		bail!("Directory already exists",)
 	}
-	let response = github::rest(
-		&format!(
-			"repos/{}/tarball",
-			if whiskers {
-				"uncenter/ctp-template-whiskers"
-			} else {
-				"catppuccin/template"
-			}
-		),
-		None,
+	let tarball = github::fetch_tarball(
+		cache,
+		if whiskers {
+			PORT_TEMPLATES[0]
+		} else {
+			PORT_TEMPLATES[1]
+		},
 	)?;
 
 	let temp = env::temp_dir();
-	let tarball = temp.join("repo.tar.gz");
-	let mut tarball_file = fs::File::create(&tarball)?;
-	io::copy(&mut response.bytes()?.as_ref(), &mut tarball_file)?;
 	let tar_gz = fs::File::open(tarball)?;
 	let tar = flate2::read::GzDecoder::new(tar_gz);
 	let mut archive = tar::Archive::new(tar);
@@ -187,12 +189,7 @@ pub fn userstyle(
 	}
 	fs::create_dir(&target)?;
 
-	let mut template = cache
-		.get_or("userstyles-template", || {
-			fetch_text(
-				"https://github.com/catppuccin/userstyles/raw/main/template/catppuccin.user.less",
-			)
-		})?
+	let mut template = userstyle_template(cache)?
 		.replace("<port-name> Catppuccin", &format!("{} Catppuccin", &name))
 		.replace(
 			"Soothing pastel theme for <port-name>",
@@ -237,3 +234,16 @@ pub fn userstyle(
 
 	Ok(())
 }
+
+/// The template that new userstyles are created from.
+///
+/// # Errors
+///
+/// Returns an error if the template can't be downloaded.
+pub fn userstyle_template(cache: &mut Cache) -> Result<String> {
+	cache.get_or("userstyles-template", || {
+		fetch_text(
+			"https://github.com/catppuccin/userstyles/raw/main/template/catppuccin.user.less",
+		)
+	})
+}
@@ -1,3 +1,6 @@
+pub mod audit;
+pub mod cache;
 pub mod init;
 pub mod query;
 pub mod whiskerify;
+pub mod whiskers;
@@ -0,0 +1,532 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use fancy_regex::Regex;
+use serde_json::Value;
+
+use super::utils::get_key;
+use crate::cli::Key;
+use crate::github::Repository;
+use crate::models::ports::Port;
+
+/// A boolean filter expression over ports, as passed to `purr query --where`.
+///
+/// ```text
+/// categories contains cli AND NOT upstreamed OR current-maintainers count == 0
+/// ```
+#[derive(Debug, Clone)]
+pub enum Filter {
+	And(Box<Filter>, Box<Filter>),
+	Or(Box<Filter>, Box<Filter>),
+	Not(Box<Filter>),
+	Predicate(Predicate),
+}
+
+#[derive(Debug, Clone)]
+pub struct Predicate {
+	key: Key,
+	count: bool,
+	comparison: Option<(Operator, Operand)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+	Eq,
+	Ne,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+	Contains,
+	Matches,
+}
+
+#[derive(Debug, Clone)]
+pub enum Operand {
+	Text(String),
+	Regex(Box<Regex>),
+}
+
+impl Filter {
+	/// Whether a port matches, given its repository for repository properties.
+	#[must_use]
+	pub fn matches(&self, port: &(String, Port), repository: Option<&Repository>) -> bool {
+		match self {
+			Filter::And(a, b) => a.matches(port, repository) && b.matches(port, repository),
+			Filter::Or(a, b) => a.matches(port, repository) || b.matches(port, repository),
+			Filter::Not(a) => !a.matches(port, repository),
+			Filter::Predicate(predicate) => predicate.matches(port, repository),
+		}
+	}
+
+	/// Every property the filter refers to.
+	#[must_use]
+	pub fn keys(&self) -> Vec<Key> {
+		match self {
+			Filter::And(a, b) | Filter::Or(a, b) => [a.keys(), b.keys()].concat(),
+			Filter::Not(a) => a.keys(),
+			Filter::Predicate(predicate) => vec![predicate.key],
+		}
+	}
+}
+
+impl Predicate {
+	fn matches(&self, port: &(String, Port), repository: Option<&Repository>) -> bool {
+		let mut value = get_key(port.clone(), self.key, repository);
+		if self.count {
+			value = Value::from(match &value {
+				Value::Null => 0,
+				Value::Array(arr) => arr.len(),
+				_ => 1,
+			});
+		}
+
+		let Some((operator, operand)) = &self.comparison else {
+			return is_truthy(&value);
+		};
+
+		match (operator, operand) {
+			(Operator::Matches, Operand::Regex(re)) => {
+				any_scalar(&value, |s| re.is_match(s).unwrap_or(false))
+			}
+			(Operator::Contains, Operand::Text(text)) => match &value {
+				Value::Array(arr) => arr.iter().any(|v| scalar_eq(v, text)),
+				_ => as_text(&value).is_some_and(|s| s.contains(text.as_str())),
+			},
+			(Operator::Eq, Operand::Text(text)) => value_eq(&value, text),
+			(Operator::Ne, Operand::Text(text)) => !value_eq(&value, text),
+			(Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge, Operand::Text(text)) => {
+				let Some(ordering) = compare(&value, text) else {
+					return false;
+				};
+				match operator {
+					Operator::Lt => ordering.is_lt(),
+					Operator::Le => ordering.is_le(),
+					Operator::Gt => ordering.is_gt(),
+					_ => ordering.is_ge(),
+				}
+			}
+			_ => unreachable!("regex operands are only created for the matches operator"),
+		}
+	}
+}
+
+fn is_truthy(value: &Value) -> bool {
+	match value {
+		Value::Null => false,
+		Value::Bool(b) => *b,
+		Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+		Value::String(s) => !s.is_empty(),
+		Value::Array(arr) => !arr.is_empty(),
+		Value::Object(obj) => !obj.is_empty(),
+	}
+}
+
+fn as_text(value: &Value) -> Option<String> {
+	match value {
+		Value::String(s) => Some(s.clone()),
+		Value::Bool(_) | Value::Number(_) => Some(value.to_string()),
+		_ => None,
+	}
+}
+
+fn any_scalar(value: &Value, f: impl Fn(&str) -> bool) -> bool {
+	match value {
+		Value::Array(arr) => arr.iter().filter_map(as_text).any(|s| f(&s)),
+		_ => as_text(value).is_some_and(|s| f(&s)),
+	}
+}
+
+fn scalar_eq(value: &Value, text: &str) -> bool {
+	match value {
+		Value::Null => text == "null",
+		Value::Number(n) => text.parse::<f64>().ok() == n.as_f64(),
+		_ => as_text(value).is_some_and(|s| s == text),
+	}
+}
+
+/// Equality against a literal; arrays compare against a comma-separated list of elements.
+fn value_eq(value: &Value, text: &str) -> bool {
+	match value {
+		Value::Array(arr) => {
+			let expected = text
+				.split(',')
+				.filter(|s| !s.is_empty())
+				.collect::<Vec<_>>();
+			arr.len() == expected.len() && arr.iter().zip(expected).all(|(v, e)| scalar_eq(v, e))
+		}
+		_ => scalar_eq(value, text),
+	}
+}
+
+/// Numeric comparison if both sides are numbers, otherwise lexicographic.
+fn compare(value: &Value, text: &str) -> Option<Ordering> {
+	match (value, text.parse::<f64>()) {
+		(Value::Number(n), Ok(expected)) => n.as_f64()?.partial_cmp(&expected),
+		_ => as_text(value).map(|s| s.as_str().cmp(text)),
+	}
+}
+
+/// An error in a filter expression, pointing at the offending part of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+	message: String,
+	input: String,
+	span: (usize, usize),
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let (start, end) = self.span;
+		let offset = self.input[..start].chars().count();
+		let width = self.input[start..end].chars().count().max(1);
+		writeln!(f, "{}", self.message)?;
+		writeln!(f, "  {}", self.input)?;
+		write!(f, "  {}{}", " ".repeat(offset), "^".repeat(width))
+	}
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+	Word(String),
+	Quoted(String),
+	Symbol(&'static str),
+	LParen,
+	RParen,
+}
+
+type Spanned = (Token, (usize, usize));
+
+const SYMBOLS: [&str; 11] = ["==", "!=", "<=", ">=", "&&", "||", "=", "<", ">", "~", "!"];
+
+fn tokenize(input: &str) -> Result<Vec<Spanned>, ParseError> {
+	let mut tokens = vec![];
+	let mut chars = input.char_indices().peekable();
+
+	while let Some(&(start, c)) = chars.peek() {
+		if c.is_whitespace() {
+			chars.next();
+		} else if c == '(' || c == ')' {
+			chars.next();
+			let token = if c == '(' {
+				Token::LParen
+			} else {
+				Token::RParen
+			};
+			tokens.push((token, (start, start + 1)));
+		} else if c == '"' || c == '\'' {
+			chars.next();
+			let mut text = String::new();
+			let mut end = None;
+			while let Some((i, ch)) = chars.next() {
+				if ch == c {
+					end = Some(i + 1);
+					break;
+				} else if ch == '\\' {
+					if let Some((_, escaped)) = chars.next() {
+						text.push(escaped);
+					}
+				} else {
+					text.push(ch);
+				}
+			}
+			let Some(end) = end else {
+				return Err(ParseError {
+					message: "unterminated string".to_string(),
+					input: input.to_string(),
+					span: (start, input.len()),
+				});
+			};
+			tokens.push((Token::Quoted(text), (start, end)));
+		} else if let Some(symbol) = SYMBOLS
+			.iter()
+			.find(|symbol| input[start..].starts_with(**symbol))
+		{
+			for _ in 0..symbol.len() {
+				chars.next();
+			}
+			tokens.push((Token::Symbol(symbol), (start, start + symbol.len())));
+		} else {
+			let mut end = start;
+			while let Some(&(i, ch)) = chars.peek() {
+				if ch.is_whitespace()
+					|| "()\"'".contains(ch)
+					|| SYMBOLS.iter().any(|symbol| input[i..].starts_with(symbol))
+				{
+					break;
+				}
+				end = i + ch.len_utf8();
+				chars.next();
+			}
+			tokens.push((Token::Word(input[start..end].to_string()), (start, end)));
+		}
+	}
+
+	Ok(tokens)
+}
+
+struct Parser<'a> {
+	input: &'a str,
+	tokens: Vec<Spanned>,
+	position: usize,
+}
+
+impl Parser<'_> {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.position).map(|(token, _)| token)
+	}
+
+	fn span(&self) -> (usize, usize) {
+		self.tokens
+			.get(self.position)
+			.map_or((self.input.len(), self.input.len()), |(_, span)| *span)
+	}
+
+	fn error(&self, message: impl Into<String>, span: (usize, usize)) -> ParseError {
+		ParseError {
+			message: message.into(),
+			input: self.input.to_string(),
+			span,
+		}
+	}
+
+	fn unexpected(&self, expected: &str) -> ParseError {
+		let span = self.span();
+		match self.peek() {
+			Some(_) => self.error(
+				format!(
+					"unexpected '{}', expected {expected}",
+					&self.input[span.0..span.1]
+				),
+				span,
+			),
+			None => self.error(
+				format!("unexpected end of expression, expected {expected}"),
+				span,
+			),
+		}
+	}
+
+	fn eat_keyword(&mut self, keyword: &str, symbol: Option<&str>) -> bool {
+		let matched = match self.peek() {
+			Some(Token::Word(word)) => word.eq_ignore_ascii_case(keyword),
+			Some(Token::Symbol(s)) => Some(*s) == symbol,
+			_ => false,
+		};
+		if matched {
+			self.position += 1;
+		}
+		matched
+	}
+
+	fn parse_or(&mut self) -> Result<Filter, ParseError> {
+		let mut filter = self.parse_and()?;
+		while self.eat_keyword("or", Some("||")) {
+			filter = Filter::Or(Box::new(filter), Box::new(self.parse_and()?));
+		}
+		Ok(filter)
+	}
+
+	fn parse_and(&mut self) -> Result<Filter, ParseError> {
+		let mut filter = self.parse_not()?;
+		while self.eat_keyword("and", Some("&&")) {
+			filter = Filter::And(Box::new(filter), Box::new(self.parse_not()?));
+		}
+		Ok(filter)
+	}
+
+	fn parse_not(&mut self) -> Result<Filter, ParseError> {
+		if self.eat_keyword("not", Some("!")) {
+			Ok(Filter::Not(Box::new(self.parse_not()?)))
+		} else {
+			self.parse_atom()
+		}
+	}
+
+	fn parse_atom(&mut self) -> Result<Filter, ParseError> {
+		match self.peek() {
+			Some(Token::LParen) => {
+				let open = self.span();
+				self.position += 1;
+				let filter = self.parse_or()?;
+				if self.peek() == Some(&Token::RParen) {
+					self.position += 1;
+					Ok(filter)
+				} else if self.peek().is_none() {
+					Err(self.error("unclosed parenthesis", open))
+				} else {
+					Err(self.unexpected("')'"))
+				}
+			}
+			Some(Token::Word(word))
+				if !word.eq_ignore_ascii_case("and") && !word.eq_ignore_ascii_case("or") =>
+			{
+				self.parse_predicate().map(Filter::Predicate)
+			}
+			_ => Err(self.unexpected("a field name or '('")),
+		}
+	}
+
+	fn parse_predicate(&mut self) -> Result<Predicate, ParseError> {
+		let span = self.span();
+		let Some(Token::Word(field)) = self.peek() else {
+			return Err(self.unexpected("a field name"));
+		};
+		let key = parse_key(field).ok_or_else(|| {
+			self.error(
+				format!(
+					"unknown field '{field}', expected one of: {}",
+					Key::value_variants()
+						.iter()
+						.filter_map(ValueEnum::to_possible_value)
+						.map(|value| value.get_name().to_string())
+						.collect::<Vec<_>>()
+						.join(", ")
+				),
+				span,
+			)
+		})?;
+		self.position += 1;
+
+		let count = self.eat_keyword("count", None);
+
+		let operator = match self.peek() {
+			Some(Token::Symbol(symbol)) => match *symbol {
+				"==" | "=" => Some(Operator::Eq),
+				"!=" => Some(Operator::Ne),
+				"<" => Some(Operator::Lt),
+				"<=" => Some(Operator::Le),
+				">" => Some(Operator::Gt),
+				">=" => Some(Operator::Ge),
+				"~" => Some(Operator::Matches),
+				_ => None,
+			},
+			Some(Token::Word(word)) if word.eq_ignore_ascii_case("contains") => {
+				Some(Operator::Contains)
+			}
+			Some(Token::Word(word)) if word.eq_ignore_ascii_case("matches") => {
+				Some(Operator::Matches)
+			}
+			_ => None,
+		};
+
+		let comparison = if let Some(operator) = operator {
+			self.position += 1;
+			let span = self.span();
+			let text = match self.peek() {
+				Some(Token::Word(text) | Token::Quoted(text)) => text.clone(),
+				_ => return Err(self.unexpected("a value")),
+			};
+			self.position += 1;
+
+			let operand = if operator == Operator::Matches {
+				Operand::Regex(Box::new(
+					Regex::new(&text)
+						.map_err(|err| self.error(format!("invalid regex: {err}"), span))?,
+				))
+			} else {
+				Operand::Text(text)
+			};
+			Some((operator, operand))
+		} else {
+			None
+		};
+
+		Ok(Predicate {
+			key,
+			count,
+			comparison,
+		})
+	}
+}
+
+fn parse_key(field: &str) -> Option<Key> {
+	match field.to_lowercase().replace('_', "-").as_str() {
+		"id" => Some(Key::Identifier),
+		"maintainers" => Some(Key::CurrentMaintainers),
+		"category" => Some(Key::Categories),
+		"platforms" => Some(Key::Platform),
+		field => Key::from_str(field, true).ok(),
+	}
+}
+
+impl FromStr for Filter {
+	type Err = ParseError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let mut parser = Parser {
+			input,
+			tokens: tokenize(input)?,
+			position: 0,
+		};
+		let filter = parser.parse_or()?;
+		if parser.peek().is_some() {
+			return Err(parser.unexpected("'AND', 'OR' or end of expression"));
+		}
+		Ok(filter)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Filter;
+	use crate::models::ports::Port;
+
+	fn port() -> (String, Port) {
+		(
+			"nvim".to_string(),
+			Port {
+				name: "Neovim".to_string(),
+				categories: vec!["code_editor".to_string(), "cli".to_string()],
+				platform: vec!["linux".to_string(), "macos".to_string()],
+				color: "green".to_string(),
+				alias: Some("neovim".to_string()),
+				current_maintainers: vec![],
+				past_maintainers: Some(vec!["pocco81".to_string()]),
+				..Default::default()
+			},
+		)
+	}
+
+	fn check(expr: &str) -> bool {
+		expr.parse::<Filter>().unwrap().matches(&port(), None)
+	}
+
+	#[test]
+	fn evaluate_expressions() {
+		assert!(check("categories contains cli"));
+		assert!(check("NOT upstreamed"));
+		assert!(check("maintainers count == 0"));
+		assert!(check(
+			"categories contains cli AND NOT upstreamed OR maintainers count == 0"
+		));
+		assert!(check("name matches '^Neo'"));
+		assert!(check("platform == linux,macos"));
+		assert!(check("past-maintainers count >= 1 && !(color != green)"));
+		assert!(!check("identifier == bat || alias contains vim && icon"));
+		assert!(check("NAME contains \"vim\""));
+	}
+
+	#[test]
+	fn report_errors() {
+		let err = "categories contains cli AND OR upstreamed"
+			.parse::<Filter>()
+			.unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"unexpected 'OR', expected a field name or '('\n  categories contains cli AND OR upstreamed\n                              ^^"
+		);
+
+		let err = "colour == green".parse::<Filter>().unwrap_err();
+		assert!(err.to_string().starts_with("unknown field 'colour'"));
+		assert!(err.to_string().ends_with("\n  ^^^^^^"));
+
+		assert!("(name == nvim".parse::<Filter>().is_err());
+		assert!("name ==".parse::<Filter>().is_err());
+		assert!("name matches '('".parse::<Filter>().is_err());
+	}
+}
@@ -0,0 +1,211 @@
+use color_eyre::eyre::{bail, Result};
+use log::warn;
+use serde::Serialize;
+
+use crate::cache::Cache;
+use crate::cli::QueryOptions;
+use crate::format::display_value;
+use crate::github::fetch_open_pull_requests;
+use crate::models::{ports, userstyles};
+use crate::sources::Sources;
+use crate::token;
+use crate::utils::{did_you_mean, suggest};
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Profile {
+	pub login: String,
+	pub collaborator: bool,
+	pub ports: Vec<String>,
+	pub userstyles: Vec<String>,
+	pub past_ports: Vec<String>,
+	pub past_userstyles: Vec<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub open_pull_requests: Option<Vec<String>>,
+}
+
+impl Profile {
+	/// Whether the user appears anywhere in the data at all.
+	#[must_use]
+	pub fn is_known(&self) -> bool {
+		self.collaborator
+			|| !self.ports.is_empty()
+			|| !self.userstyles.is_empty()
+			|| !self.past_ports.is_empty()
+			|| !self.past_userstyles.is_empty()
+	}
+}
+
+fn contains_login(logins: &[String], login: &str) -> bool {
+	logins.iter().any(|l| l.eq_ignore_ascii_case(login))
+}
+
+/// Identifiers of the entries currently and previously maintained by the login, each sorted.
+fn maintained<'a>(
+	entries: impl Iterator<Item = (&'a String, &'a Vec<String>, &'a Option<Vec<String>>)>,
+	login: &str,
+) -> (Vec<String>, Vec<String>) {
+	let (mut current, mut past) = (vec![], vec![]);
+	for (identifier, current_maintainers, past_maintainers) in entries {
+		if contains_login(current_maintainers, login) {
+			current.push(identifier.clone());
+		}
+		if past_maintainers
+			.as_ref()
+			.is_some_and(|past| contains_login(past, login))
+		{
+			past.push(identifier.clone());
+		}
+	}
+	current.sort();
+	past.sort();
+	(current, past)
+}
+
+/// Build the profile of a maintainer from the ports and/or userstyles data, matching the login case-insensitively.
+#[must_use]
+pub fn profile(
+	login: &str,
+	ports: Option<&ports::Root>,
+	userstyles: Option<&userstyles::Root>,
+) -> Profile {
+	let (current_ports, past_ports) = ports.map_or_else(Default::default, |root| {
+		maintained(
+			root.ports
+				.iter()
+				.map(|(id, p)| (id, &p.current_maintainers, &p.past_maintainers)),
+			login,
+		)
+	});
+	let (current_userstyles, past_userstyles) = userstyles.map_or_else(Default::default, |root| {
+		maintained(
+			root.userstyles
+				.iter()
+				.map(|(id, u)| (id, &u.current_maintainers, &u.past_maintainers)),
+			login,
+		)
+	});
+
+	Profile {
+		login: known_logins(ports, userstyles)
+			.into_iter()
+			.find(|known| known.eq_ignore_ascii_case(login))
+			.unwrap_or_else(|| login.to_string()),
+		collaborator: ports.is_some_and(|root| contains_login(&root.collaborators, login))
+			|| userstyles.is_some_and(|root| contains_login(&root.collaborators, login)),
+		ports: current_ports,
+		userstyles: current_userstyles,
+		past_ports,
+		past_userstyles,
+		open_pull_requests: None,
+	}
+}
+
+/// Every login mentioned in the data, for suggesting alternatives to unknown logins.
+#[must_use]
+pub fn known_logins(
+	ports: Option<&ports::Root>,
+	userstyles: Option<&userstyles::Root>,
+) -> Vec<String> {
+	let mut logins = vec![];
+	if let Some(root) = ports {
+		logins.extend(root.collaborators.iter().cloned());
+		for port in root.ports.values() {
+			logins.extend(port.current_maintainers.iter().cloned());
+			logins.extend(port.past_maintainers.iter().flatten().cloned());
+		}
+	}
+	if let Some(root) = userstyles {
+		logins.extend(root.collaborators.iter().cloned());
+		for userstyle in root.userstyles.values() {
+			logins.extend(userstyle.current_maintainers.iter().cloned());
+			logins.extend(userstyle.past_maintainers.iter().flatten().cloned());
+		}
+	}
+	logins.sort();
+	logins.dedup();
+	logins
+}
+
+/// Display the profile of a maintainer, including their open pull requests if a GitHub token is available.
+///
+/// # Errors
+///
+/// Returns an error if the data can't be loaded, the login is unknown or the pull requests can't be fetched.
+pub fn handle(
+	cache: &mut Cache,
+	sources: &Sources,
+	login: &str,
+	token: Option<String>,
+	options: &QueryOptions,
+) -> Result<()> {
+	let ports = if options.only_userstyles {
+		None
+	} else {
+		Some(sources.ports(cache)?)
+	};
+	let userstyles = if options.userstyles || options.only_userstyles {
+		Some(sources.userstyles(cache)?)
+	} else {
+		None
+	};
+
+	let mut profile = profile(login, ports.as_ref(), userstyles.as_ref());
+	if !profile.is_known() {
+		let logins = known_logins(ports.as_ref(), userstyles.as_ref());
+		bail!(
+			"no maintainer or collaborator with the login '{login}'{}",
+			did_you_mean(
+				&suggest(login, logins.iter().map(String::as_str))
+					.into_iter()
+					.take(3)
+					.map(|(login, _)| login)
+					.collect::<Vec<_>>()
+			)
+		);
+	}
+	let explicit = token.as_deref().is_some_and(|token| !token.is_empty());
+	if let Some(token) = token::resolve(token, &sources.github) {
+		match fetch_open_pull_requests(cache, &sources.github, login, token) {
+			Ok(pull_requests) => {
+				profile.open_pull_requests =
+					Some(pull_requests.into_iter().map(|pr| pr.html_url).collect());
+			}
+			// A discovered token may lack access or be rate limited, and the rest of the profile needs no token.
+			Err(err) if !explicit => {
+				warn!("leaving out open pull requests, as fetching them failed: {err}");
+			}
+			Err(err) => return Err(err),
+		}
+	}
+
+	display_value(profile, options.format)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{profile, Profile};
+	use crate::cmd::query::tests::fixtures;
+
+	#[test]
+	fn build_maintainer_profile() {
+		let (mut cache, sources) = fixtures();
+		let ports = sources.ports(&mut cache).unwrap();
+		let userstyles = sources.userstyles(&mut cache).unwrap();
+
+		assert_eq!(
+			profile("UNCENTER", Some(&ports), Some(&userstyles)),
+			Profile {
+				login: "uncenter".to_string(),
+				collaborator: true,
+				ports: vec!["purr".to_string()],
+				userstyles: vec!["github".to_string()],
+				..Default::default()
+			}
+		);
+
+		let pocco81 = profile("pocco81", Some(&ports), None);
+		assert_eq!(pocco81.past_ports, vec!["alacritty", "nvim"]);
+		assert!(!profile("isabelroses", Some(&ports), None).is_known());
+	}
+}
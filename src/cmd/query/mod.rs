@@ -1,276 +1,647 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
 
-use color_eyre::eyre::{eyre, Context, Result};
+use color_eyre::eyre::{bail, Result, WrapErr};
+use log::warn;
 use serde_json::Value;
 
 use crate::cache::Cache;
-use crate::cli::{Key, Query, WhiskersCustomProperty};
-use crate::github::{self, fetch_all_repositories, fetch_whiskers_status, RepositoryResponse};
-use crate::models::{self, ports::Port};
-use crate::utils::fetch_yaml;
+use crate::cli::{HasQuery, Key, Query, QueryOptions};
+use crate::format::{display, display_grouped, display_value};
+use crate::github::{fetch_all_repositories, Repository};
+use crate::models::ports::Port;
+use crate::sources::Sources;
+use crate::token;
+use crate::utils::did_you_mean;
 
+pub mod filter;
+pub mod maintainer;
+pub mod orphaned;
+pub mod property;
+pub mod stars;
+pub mod stats;
 mod utils;
-use utils::{display_json_or_count, get_key, is_booleanish_match, matches_current_maintainer};
+pub mod whiskers;
+use filter::Filter;
+use utils::{
+	closest_ports, compare_values, find_port, get_key, is_booleanish_match,
+	matches_current_maintainer, matches_port_name, port_similarity,
+};
 
-pub fn query(
+/// Whether an entry of the query data comes from ports.yml or userstyles.yml.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+	Port,
+	Userstyle,
+}
+
+/// Load ports and/or userstyles (converted to ports) from the given data sources.
+///
+/// # Errors
+///
+/// Returns an error if a data source can't be read or parsed.
+pub fn load_data(
 	cache: &mut Cache,
-	command: Option<Query>,
-	r#for: Option<String>,
-	count: bool,
-	get: Vec<Key>,
+	sources: &Sources,
 	include_userstyles: bool,
 	only_userstyles: bool,
-) -> Result<()> {
-	let ports = cache
-		.get_or("ports-yml", || {
-			fetch_yaml::<models::ports::Root>(
-				"https://github.com/catppuccin/catppuccin/raw/main/resources/ports.yml",
-			)
-		})?
-		.ports
-		.into_iter()
-		.collect::<Vec<_>>();
+) -> Result<Vec<(String, Port)>> {
+	Ok(
+		load_entries(cache, sources, include_userstyles, only_userstyles)?
+			.into_iter()
+			.map(|(_, port)| port)
+			.collect(),
+	)
+}
 
-	let userstyles = cache
-		.get_or("userstyles-yml", || {
-			fetch_yaml::<models::userstyles::Root>(
-				"https://github.com/catppuccin/userstyles/raw/main/scripts/userstyles.yml",
-			)
-		})?
-		.userstyles
-		.into_iter()
-		.map(|(key, userstyle)| (key, Port::from(userstyle)))
-		.collect::<Vec<_>>();
+/// Like [`load_data`], but keeping track of where each entry comes from.
+fn load_entries(
+	cache: &mut Cache,
+	sources: &Sources,
+	include_userstyles: bool,
+	only_userstyles: bool,
+) -> Result<Vec<(Origin, (String, Port))>> {
+	let ports = if only_userstyles {
+		vec![]
+	} else {
+		sources
+			.ports(cache)?
+			.ports
+			.into_iter()
+			.map(|port| (Origin::Port, port))
+			.collect::<Vec<_>>()
+	};
 
-	let data = if only_userstyles {
-		userstyles
-	} else if include_userstyles {
-		[ports, userstyles].concat()
+	let userstyles = if include_userstyles || only_userstyles {
+		sources
+			.userstyles(cache)?
+			.userstyles
+			.into_iter()
+			.map(|(key, userstyle)| (Origin::Userstyle, (key, Port::from(userstyle))))
+			.collect::<Vec<_>>()
 	} else {
-		ports
+		vec![]
+	};
+
+	Ok([ports, userstyles].concat())
+}
+
+/// Join ports (but not userstyles) with their repository in the organization by identifier.
+fn join_repositories<S: BuildHasher>(
+	entries: &[(Origin, (String, Port))],
+	repositories: &HashMap<String, Repository, S>,
+) -> HashMap<String, Repository> {
+	entries
+		.iter()
+		.filter(|(origin, _)| *origin == Origin::Port)
+		.filter_map(|(_, (identifier, _))| {
+			let repository = repositories.get(identifier)?;
+			Some((identifier.clone(), repository.clone()))
+		})
+		.collect()
+}
+
+/// Ports (and/or userstyles) matching the filter of a query, in the requested order, along with the repository data joined with them.
+struct Selection {
+	entries: Vec<(Origin, (String, Port))>,
+	repositories: HashMap<String, Repository>,
+}
+
+impl Selection {
+	/// Load the entries to query that match `--where`, like [`Selection::load_unfiltered`].
+	fn load(
+		cache: &mut Cache,
+		sources: &Sources,
+		keys: &[Key],
+		options: &QueryOptions,
+	) -> Result<Self> {
+		let mut selection = Self::load_unfiltered(cache, sources, keys, options)?;
+		let entries = std::mem::take(&mut selection.entries)
+			.into_iter()
+			.filter(|(_, port)| selection.matches(port, options))
+			.collect();
+		Ok(Selection {
+			entries,
+			..selection
+		})
+	}
+
+	/// Load all entries to query, fetching repository data only when a repository property is actually used, either in `keys` or in the options.
+	fn load_unfiltered(
+		cache: &mut Cache,
+		sources: &Sources,
+		keys: &[Key],
+		options: &QueryOptions,
+	) -> Result<Self> {
+		let repository_keys = keys
+			.iter()
+			.copied()
+			.chain([options.sort_by])
+			.chain(options.group_by)
+			.chain(
+				options
+					.filter
+					.as_ref()
+					.map(Filter::keys)
+					.unwrap_or_default(),
+			)
+			.filter(|key| key.is_repository())
+			.collect::<Vec<_>>();
+		let repositories = match repository_keys.first() {
+			None => None,
+			Some(key) => Some(repositories_by_name(
+				cache,
+				sources,
+				&token::require(options.token.clone(), &sources.github, cache)
+					.wrap_err_with(|| format!("the {key} property requires a GitHub token"))?,
+			)?),
+		};
+
+		let mut entries =
+			load_entries(cache, sources, options.userstyles, options.only_userstyles)?;
+		let selection = Selection {
+			repositories: repositories
+				.as_ref()
+				.map(|repositories| join_repositories(&entries, repositories))
+				.unwrap_or_default(),
+			entries: vec![],
+		};
+		// Sort by identifier first so that ties in the requested property have a stable order.
+		entries.sort_by(|(_, a), (_, b)| a.0.cmp(&b.0));
+		entries.sort_by(|(_, a), (_, b)| {
+			let ordering = compare_values(
+				&get_key(a.clone(), options.sort_by, selection.repository(&a.0)),
+				&get_key(b.clone(), options.sort_by, selection.repository(&b.0)),
+			);
+			if options.desc {
+				ordering.reverse()
+			} else {
+				ordering
+			}
+		});
+
+		Ok(Selection {
+			entries,
+			..selection
+		})
+	}
+
+	fn repository(&self, identifier: &str) -> Option<&Repository> {
+		self.repositories.get(identifier)
+	}
+
+	/// Whether a port matches `--where`, if given.
+	fn matches(&self, port: &(String, Port), options: &QueryOptions) -> bool {
+		options
+			.filter
+			.as_ref()
+			.is_none_or(|filter| filter.matches(port, self.repository(&port.0)))
 	}
-	.into_iter();
 
-	fn extract_port_data(port: (String, Port), keys: &[Key]) -> HashMap<Key, Value> {
-		keys.iter()
-			.map(|&key| (key, get_key(port.clone(), key)))
-			.collect()
+	fn ports(&self) -> Vec<(String, Port)> {
+		self.entries.iter().map(|(_, port)| port.clone()).collect()
 	}
 
+	/// Display the given keys of some of the selected ports, grouped if requested.
+	fn display(
+		&self,
+		ports: Vec<(String, Port)>,
+		keys: &[Key],
+		count: bool,
+		options: &QueryOptions,
+	) -> Result<()> {
+		let ports = ports.into_iter().take(options.limit.unwrap_or(usize::MAX));
+
+		if let Some(group_by) = options.group_by {
+			let mut groups: BTreeMap<String, Vec<BTreeMap<Key, Value>>> = BTreeMap::new();
+			for port in ports {
+				let repository = self.repository(&port.0);
+				for bucket in buckets(&get_key(port.clone(), group_by, repository)) {
+					groups
+						.entry(bucket)
+						.or_default()
+						.push(extract_port_data(&port, keys, repository));
+				}
+			}
+			display_grouped(groups, &group_by.to_string(), count, options.format)
+		} else {
+			display(
+				ports
+					.map(|port| extract_port_data(&port, keys, self.repository(&port.0)))
+					.collect(),
+				count,
+				options.format,
+			)
+		}
+	}
+}
+
+/// Repositories of the organization by name.
+fn repositories_by_name(
+	cache: &mut Cache,
+	sources: &Sources,
+	token: &str,
+) -> Result<HashMap<String, Repository>> {
+	Ok(fetch_all_repositories(cache, &sources.github, token)?
+		.into_iter()
+		.flatten()
+		.map(|repository| (repository.name.clone(), repository))
+		.collect())
+}
+
+/// Run a query, or list the ports (or a single port) when no subcommand is given.
+///
+/// # Errors
+///
+/// Returns an error if the data can't be loaded or fetched, a port or maintainer can't be found, or the results can't be displayed.
+pub fn query(
+	cache: &mut Cache,
+	sources: &Sources,
+	command: Option<Query>,
+	r#for: Option<String>,
+	count: bool,
+	get: Vec<Key>,
+	options: QueryOptions,
+) -> Result<()> {
 	match command {
-		Some(Query::Maintained { by, options }) => {
-			let result = data
+		Some(Query::Maintained { by, options: extra }) => {
+			let selection = Selection::load(cache, sources, &extra.get, &options)?;
+			let result = selection
+				.ports()
+				.into_iter()
 				.filter(|port| {
 					let current_maintainers = &port.1.current_maintainers;
-					let matches = matches_current_maintainer(current_maintainers, &by);
-
-					if options.not {
-						!matches
-					} else {
-						matches
-					}
+					matches_current_maintainer(current_maintainers, &by) != extra.not
 				})
-				.map(|port| extract_port_data(port, &options.get))
-				.collect::<Vec<_>>();
+				.collect();
 
-			display_json_or_count(result, options.count)?;
+			selection.display(result, &extra.get, extra.count, &options)
 		}
-		Some(Query::Has {
-			name,
-			categories,
-			upstreamed,
-			platform,
-			icon,
-			color,
-			alias,
-			url,
-			options,
-		}) => {
-			let result = data
-				.filter(|port| {
-					let matches: bool = {
-						if let Some(name) = &name {
-							*name == port.0 || &port.1.name == name
-						} else {
-							true
-						}
-					} && {
-						if let Some(upstreamed) = &upstreamed {
-							*upstreamed == port.1.upstreamed.unwrap_or(false)
-						} else {
-							true
-						}
-					} && {
-						if let Some(platform) = &platform {
-							platform.iter().all(|p| port.1.platform.contains(p))
-						} else {
-							true
-						}
-					} && {
-						if let Some(categories) = &categories {
-							categories.iter().all(|c| port.1.categories.contains(c))
-						} else {
-							true
-						}
-					} && {
-						if let Some(icon) = &icon {
-							let value = &port.1.icon;
-							is_booleanish_match(value.clone(), icon)
-						} else {
-							true
-						}
-					} && {
-						if let Some(color) = &color {
-							color.parse().unwrap_or_else(|_| *color == port.1.color)
-						} else {
-							true
-						}
-					} && {
-						if let Some(alias) = &alias {
-							let value = &port.1.alias;
-							is_booleanish_match(value.to_owned(), alias)
-						} else {
-							true
-						}
-					} && {
-						if let Some(url) = &url {
-							is_booleanish_match(port.1.url.clone(), url)
-						} else {
-							true
-						}
-					};
-
-					if options.not {
-						!matches
-					} else {
-						matches
-					}
-				})
-				.map(|port| extract_port_data(port, &options.get))
-				.collect::<Vec<_>>();
+		Some(Query::Has(query)) => {
+			let selection = Selection::load(cache, sources, &query.options.get, &options)?;
+			let result = has(&selection.ports(), &query, options.fuzzy);
 
-			display_json_or_count(result, options.count)?;
+			selection.display(result, &query.options.get, query.options.count, &options)
+		}
+		Some(Query::Stats { by }) => {
+			let selection = Selection::load(cache, sources, &[], &options)?;
+			stats::handle(&selection.ports(), by, count, &options)
+		}
+		Some(Query::Maintainer { login, token }) => {
+			reject_filter(&options, "maintainer")?;
+			maintainer::handle(cache, sources, &login, token, &options)
+		}
+		Some(Query::Orphaned(query)) => {
+			let selection = Selection::load(cache, sources, &[], &options)?;
+			orphaned::handle(cache, sources, selection.entries, query, count, &options)
 		}
-		Some(Query::Stars {
-			r#for,
-			archived,
-			token,
-		}) => {
-			if let Some(repository) = r#for {
-				let res = github::rest(&format!("repos/catppuccin/{repository}"), Some(token))?
-					.json::<RepositoryResponse>()?;
-
-				println!("{}", res.stargazers_count);
+		Some(Query::Stars(query)) => {
+			reject_filter(&options, "stars")?;
+			stars::handle(cache, sources, query, &options)
+		}
+		Some(Query::Whiskers(query)) => {
+			reject_filter(&options, "whiskers")?;
+			whiskers::handle(cache, sources, query, options.format)
+		}
+		Some(Query::Property(query)) => {
+			reject_filter(&options, "property")?;
+			property::handle(cache, &sources.github, query, options.format)
+		}
+		None => {
+			let Some(r#for) = r#for else {
+				let selection = Selection::load(cache, sources, &get, &options)?;
+				return selection.display(selection.ports(), &get, count, &options);
+			};
+
+			// The port is looked up among all of them, so that `--where` can't make it seem missing.
+			let selection = Selection::load_unfiltered(cache, sources, &get, &options)?;
+			let data = selection.ports();
+			if let Some(port) = find_port(&data, &r#for) {
+				if !selection.matches(port, &options) {
+					bail!("the port '{}' doesn't match the --where filter", port.0);
+				}
+				display_value(
+					extract_port_data(port, &get, selection.repository(&port.0)),
+					options.format,
+				)
+			} else if options.fuzzy {
+				selection.display(
+					closest_ports(&data, &r#for)
+						.into_iter()
+						.filter(|port| selection.matches(port, &options))
+						.cloned()
+						.collect(),
+					&get,
+					count,
+					&options,
+				)
 			} else {
-				let repositories = fetch_all_repositories(cache, &token)?;
-
-				let stars: i64 = repositories
-					.iter()
-					.flatten()
-					.filter_map(|r| {
-						let count = r.stargazer_count;
-						let matches = archived == r.is_archived;
-
-						if matches {
-							Some(count)
-						} else {
-							None
-						}
-					})
-					.sum();
-
-				println!("{stars}");
+				bail!(
+					"no port with the name '{}'{}",
+					r#for,
+					did_you_mean(&suggestions(&data, &r#for))
+				);
 			}
 		}
-		Some(Query::Whiskers {
-			r#for,
-			is,
-			not,
-			count,
-			token,
-		}) => {
-			if let Some(repository) = r#for {
-				let status = fetch_whiskers_status(cache, &repository, token)?;
-
-				println!(
-					"{}",
-					if let Some(is) = is {
-						let matches = status == is.to_string();
-						if not { !matches } else { matches }.to_string()
-					} else {
-						status
-					}
-				);
-			} else {
-				let mut found_true = 0;
-				let mut found_false = 0;
-				let mut found_na = 0;
-
-				let repositories = fetch_all_repositories(cache, &token)?;
-				let result = repositories
-					.iter()
-					.flatten()
-					.filter(|repo| !repo.is_archived)
-					.filter_map(|repository| {
-						let status =
-							fetch_whiskers_status(cache, &repository.name, token.clone()).unwrap();
-
-						if status == WhiskersCustomProperty::True.to_string() {
-							found_true += 1;
-						} else if status == WhiskersCustomProperty::False.to_string() {
-							found_false += 1;
-						} else {
-							found_na += 1;
-						}
-
-						if let Some(is) = is {
-							if status == is.to_string() {
-								Some(Value::String(repository.name.to_string()))
-							} else {
-								None
-							}
-						} else {
-							None
-						}
-					})
-					.collect::<Vec<_>>();
-
-				if is.is_none() {
-					// TODO: Improve Whiskers status output formatting.
-					println!(
-						"true: {}, false: {}, n/a: {} ({:.2}%)",
-						found_true,
-						found_false,
-						found_na,
-						(found_true as f32 / (found_true + found_false) as f32) * 100.0
-					);
+	}
+}
+
+/// Fail for subcommands that don't list ports, and so have nothing to apply `--where` to.
+fn reject_filter(options: &QueryOptions, subcommand: &str) -> Result<()> {
+	if options.filter.is_some() {
+		bail!("--where can't be used with `purr query {subcommand}`");
+	}
+	Ok(())
+}
+
+/// Ports with the fields a `has` query asks for.
+fn has(data: &[(String, Port)], query: &HasQuery, fuzzy: bool) -> Vec<(String, Port)> {
+	let HasQuery {
+		name,
+		categories,
+		upstreamed,
+		platform,
+		icon,
+		color,
+		alias,
+		url,
+		options,
+	} = query;
+
+	let result = if let (Some(name), true) = (name, fuzzy) {
+		closest_ports(data, name).into_iter().cloned().collect()
+	} else {
+		data.to_vec()
+	}
+	.into_iter()
+	.filter(|port| {
+		let matches: bool = {
+			if let Some(name) = name {
+				if fuzzy {
+					port_similarity(port, name).is_some()
 				} else {
-					display_json_or_count(result, count)?;
+					matches_port_name(port, name)
 				}
+			} else {
+				true
 			}
-		}
-		None => {
-			if let Some(r#for) = r#for {
-				println!(
-					"{}",
-					serde_json::to_string_pretty(
-						data.filter(|port| port.0.to_lowercase() == r#for.to_lowercase())
-							.map(|port| extract_port_data(port, &get))
-							.collect::<Vec<_>>()
-							.first()
-							.ok_or_else(|| eyre!("no port with the name '{}'", r#for))?
-					)
-					.context("Failed to serialize results")?
-				);
+		} && {
+			if let Some(upstreamed) = upstreamed {
+				*upstreamed == port.1.upstreamed.unwrap_or(false)
 			} else {
-				display_json_or_count(
-					data.map(|port| extract_port_data(port, &get))
-						.collect::<Vec<_>>(),
-					count,
-				)?;
+				true
 			}
+		} && {
+			if let Some(platform) = platform {
+				platform.iter().all(|p| port.1.platform.contains(p))
+			} else {
+				true
+			}
+		} && {
+			if let Some(categories) = categories {
+				categories.iter().all(|c| port.1.categories.contains(c))
+			} else {
+				true
+			}
+		} && {
+			if let Some(icon) = icon {
+				let value = &port.1.icon;
+				is_booleanish_match(value.clone(), icon)
+			} else {
+				true
+			}
+		} && {
+			if let Some(color) = color {
+				color.parse().unwrap_or_else(|_| *color == port.1.color)
+			} else {
+				true
+			}
+		} && {
+			if let Some(alias) = alias {
+				let value = &port.1.alias;
+				is_booleanish_match(value.to_owned(), alias)
+			} else {
+				true
+			}
+		} && {
+			if let Some(url) = url {
+				is_booleanish_match(port.1.url.clone(), url)
+			} else {
+				true
+			}
+		};
+
+		matches != options.not
+	})
+	.collect::<Vec<_>>();
+
+	if let (Some(name), false) = (name, fuzzy) {
+		if find_port(data, name).is_none() {
+			warn!(
+				"no port with the name '{name}'{}",
+				did_you_mean(&suggestions(data, name))
+			);
 		}
 	}
 
-	Ok(())
+	result
+}
+
+fn extract_port_data(
+	port: &(String, Port),
+	keys: &[Key],
+	repository: Option<&Repository>,
+) -> BTreeMap<Key, Value> {
+	keys.iter()
+		.map(|&key| (key, get_key(port.clone(), key, repository)))
+		.collect()
+}
+
+/// Identifiers of up to three ports that closely match a name.
+fn suggestions<'a>(ports: &'a [(String, Port)], name: &str) -> Vec<&'a str> {
+	closest_ports(ports, name)
+		.into_iter()
+		.take(3)
+		.map(|(identifier, _)| identifier.as_str())
+		.collect()
+}
+
+/// The groups a value belongs to when grouping results; list values place a result in a group for each item.
+fn buckets(value: &Value) -> Vec<String> {
+	match value {
+		Value::Array(arr) if !arr.is_empty() => arr.iter().flat_map(buckets).collect(),
+		Value::Null | Value::Array(_) => vec!["none".to_string()],
+		Value::String(s) => vec![s.clone()],
+		_ => vec![value.to_string()],
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+	use std::{env, path::PathBuf};
+
+	use serde_json::json;
+
+	use super::utils::{closest_ports, compare_values, find_port, get_key};
+	use super::{buckets, join_repositories, load_data, load_entries, suggestions, Selection};
+	use crate::cache::Cache;
+	use clap::Parser;
+
+	use crate::cli::{Cli, Commands, Key};
+	use crate::github::repositories::{
+		RepositoriesOrganizationRepositoriesNodesIssues as Issues,
+		RepositoriesOrganizationRepositoriesNodesPrimaryLanguage as Language,
+	};
+	use crate::github::Repository;
+	use crate::sources::{Source, Sources};
+
+	pub(super) fn fixtures() -> (Cache, Sources) {
+		let fixture = |name: &str| {
+			Source::Path(
+				PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+					.join("tests/fixtures")
+					.join(name),
+			)
+		};
+		(
+			Cache::new(env::temp_dir().join("purr-tests/store.json"), true, 0),
+			Sources {
+				ports: fixture("ports.yml"),
+				userstyles: fixture("userstyles.yml"),
+				..Sources::default()
+			},
+		)
+	}
+
+	#[test]
+	fn load_from_local_sources() {
+		let (mut cache, sources) = fixtures();
+
+		let ports = load_data(&mut cache, &sources, false, false).unwrap();
+		assert_eq!(ports.len(), 4);
+		assert!(ports
+			.iter()
+			.any(|(id, port)| id == "nvim" && port.name == "Neovim"));
+
+		let userstyles = load_data(&mut cache, &sources, false, true).unwrap();
+		assert_eq!(userstyles.len(), 2);
+		assert!(userstyles
+			.iter()
+			.all(|(_, port)| port.platform == vec!["web".to_string()]));
+
+		assert_eq!(
+			load_data(&mut cache, &sources, true, false).unwrap().len(),
+			6
+		);
+	}
+
+	#[test]
+	fn join_repository_data() {
+		let (mut cache, sources) = fixtures();
+		let repository = |name: &str, issues| {
+			(
+				name.to_string(),
+				Repository {
+					name: name.to_string(),
+					primary_language: Some(Language {
+						name: "Lua".to_string(),
+					}),
+					issues: Issues {
+						total_count: issues,
+					},
+					..Default::default()
+				},
+			)
+		};
+		let repositories = HashMap::from([repository("nvim", 3), repository("github", 1)]);
+
+		let entries = load_entries(&mut cache, &sources, true, false).unwrap();
+		let joined = join_repositories(&entries, &repositories);
+		let get = |identifier: &str, key| {
+			let (_, port) = entries
+				.iter()
+				.find(|(_, (id, _))| id == identifier)
+				.unwrap();
+			get_key(port.clone(), key, joined.get(identifier))
+		};
+		assert_eq!(get("nvim", Key::Language), json!("Lua"));
+		assert_eq!(get("nvim", Key::OpenIssues), json!(3));
+		assert_eq!(get("nvim", Key::Topics), json!([]));
+		assert_eq!(get("bat", Key::Language), json!(null));
+		// Userstyles are never joined with a repository, even one with the same name.
+		assert_eq!(get("github", Key::OpenIssues), json!(null));
+	}
+
+	#[test]
+	fn sort_and_group_values() {
+		let mut values = vec![
+			json!(["b"]),
+			json!("Neovim"),
+			json!(null),
+			json!(["a", "c"]),
+			json!("bat"),
+			json!(true),
+		];
+		values.sort_by(compare_values);
+		assert_eq!(
+			values,
+			vec![
+				json!(null),
+				json!(true),
+				json!("bat"),
+				json!("Neovim"),
+				json!(["a", "c"]),
+				json!(["b"]),
+			]
+		);
+
+		assert_eq!(
+			buckets(&json!(["cli", "development"])),
+			vec!["cli", "development"]
+		);
+		assert_eq!(buckets(&json!([])), vec!["none"]);
+		assert_eq!(buckets(&json!(false)), vec!["false"]);
+	}
+
+	#[test]
+	fn filter_after_loading() {
+		let (mut cache, sources) = fixtures();
+		let Commands::Query { options, .. } =
+			Cli::try_parse_from(["purr", "query", "--where", "upstreamed"])
+				.unwrap()
+				.command
+		else {
+			panic!("expected a query");
+		};
+
+		// `--for` is resolved against all ports, so `--where` applies to the port found.
+		let all = Selection::load_unfiltered(&mut cache, &sources, &[], &options).unwrap();
+		let nvim = find_port(&all.ports(), "nvim").cloned().unwrap();
+		assert!(!all.matches(&nvim, &options));
+
+		let selection = Selection::load(&mut cache, &sources, &[], &options).unwrap();
+		assert_eq!(selection.entries.len(), 1);
+		assert_eq!(selection.entries[0].1 .0, "purr");
+	}
+
+	#[test]
+	fn resolve_port_names() {
+		let (mut cache, sources) = fixtures();
+		let ports = load_data(&mut cache, &sources, true, false).unwrap();
+
+		assert_eq!(
+			find_port(&ports, "NeoVim").map(|p| p.0.as_str()),
+			Some("nvim")
+		);
+		assert_eq!(
+			find_port(&ports, "purr").map(|p| p.0.as_str()),
+			Some("purr")
+		);
+		assert!(find_port(&ports, "alacrity").is_none());
+
+		assert_eq!(closest_ports(&ports, "alacrity")[0].0, "alacritty");
+		assert_eq!(suggestions(&ports, "githib"), vec!["github"]);
+		assert!(suggestions(&ports, "xyzzy").is_empty());
+	}
 }
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+use color_eyre::eyre::Result;
+use serde::Serialize;
+
+use super::{repositories_by_name, Origin};
+use crate::cache::Cache;
+use crate::cli::{OrphanedQuery, QueryOptions};
+use crate::format::display;
+use crate::github::Repository;
+use crate::models::ports::Port;
+use crate::sources::Sources;
+use crate::token;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Orphan {
+	pub identifier: String,
+	pub name: String,
+	pub userstyle: bool,
+	pub past_maintainers: Vec<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub last_pushed: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub open_issues: Option<i64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub open_pull_requests: Option<i64>,
+}
+
+/// List ports without any current maintainers. If repository data is given, each port is joined with its repository (by identifier) to include activity information.
+#[must_use]
+pub fn report<S: BuildHasher>(
+	ports: impl IntoIterator<Item = (Origin, (String, Port))>,
+	repositories: Option<&HashMap<String, Repository, S>>,
+) -> Vec<Orphan> {
+	ports
+		.into_iter()
+		.filter(|(_, (_, port))| port.current_maintainers.is_empty())
+		.map(|(origin, port)| {
+			let userstyle = origin == Origin::Userstyle;
+			let repository = repositories
+				.filter(|_| !userstyle)
+				.and_then(|repositories| repositories.get(&port.0));
+
+			Orphan {
+				userstyle,
+				last_pushed: repository.and_then(|r| r.pushed_at.clone()),
+				open_issues: repository.map(|r| r.issues.total_count),
+				open_pull_requests: repository.map(|r| r.pull_requests.total_count),
+				identifier: port.0,
+				name: port.1.name,
+				past_maintainers: port.1.past_maintainers.unwrap_or_default(),
+			}
+		})
+		.collect()
+}
+
+/// Sort orphans by staleness, least recently pushed to first. Orphans without activity information are listed last.
+pub fn sort_by_staleness(orphans: &mut [Orphan]) {
+	// ISO 8601 timestamps in UTC sort correctly as strings.
+	orphans.sort_by(|a, b| match (&a.last_pushed, &b.last_pushed) {
+		(Some(a), Some(b)) => a.cmp(b),
+		(a, b) => b.is_some().cmp(&a.is_some()),
+	});
+}
+
+/// Display the ports and userstyles without any current maintainers, optionally with the activity of their repositories.
+///
+/// # Errors
+///
+/// Returns an error if the repositories can't be fetched or the results can't be displayed.
+pub fn handle(
+	cache: &mut Cache,
+	sources: &Sources,
+	entries: Vec<(Origin, (String, Port))>,
+	query: OrphanedQuery,
+	count: bool,
+	options: &QueryOptions,
+) -> Result<()> {
+	let repositories = if query.activity {
+		Some(repositories_by_name(
+			cache,
+			sources,
+			&token::require(query.token, &sources.github, cache)?,
+		)?)
+	} else {
+		None
+	};
+	let mut orphans = report(entries, repositories.as_ref());
+	if query.stalest {
+		sort_by_staleness(&mut orphans);
+	}
+
+	display(
+		orphans
+			.into_iter()
+			.take(options.limit.unwrap_or(usize::MAX))
+			.collect(),
+		count,
+		options.format,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use super::{report, sort_by_staleness};
+	use crate::cmd::query::Origin;
+	use crate::github::repositories::{
+		RepositoriesOrganizationRepositoriesNodesIssues as Issues,
+		RepositoriesOrganizationRepositoriesNodesPullRequests as PullRequests,
+	};
+	use crate::github::Repository;
+	use crate::models::ports::Port;
+
+	fn port(origin: Origin, identifier: &str, maintainers: &[&str]) -> (Origin, (String, Port)) {
+		(
+			origin,
+			(
+				identifier.to_string(),
+				Port {
+					name: identifier.to_uppercase(),
+					current_maintainers: maintainers.iter().map(ToString::to_string).collect(),
+					past_maintainers: Some(vec!["someone".to_string()]),
+					..Default::default()
+				},
+			),
+		)
+	}
+
+	fn repository(name: &str, pushed_at: &str) -> (String, Repository) {
+		(
+			name.to_string(),
+			Repository {
+				name: name.to_string(),
+				pushed_at: Some(pushed_at.to_string()),
+				issues: Issues { total_count: 2 },
+				pull_requests: PullRequests { total_count: 1 },
+				..Default::default()
+			},
+		)
+	}
+
+	#[test]
+	fn report_orphans_by_staleness() {
+		let repositories = HashMap::from([
+			repository("alacritty", "2024-05-01T00:00:00Z"),
+			repository("kitty", "2022-01-01T00:00:00Z"),
+		]);
+		let mut orphans = report(
+			vec![
+				port(Origin::Port, "alacritty", &[]),
+				port(Origin::Port, "bat", &["sgoudham"]),
+				port(Origin::Port, "kitty", &[]),
+				port(Origin::Userstyle, "wikipedia", &[]),
+			],
+			Some(&repositories),
+		);
+		sort_by_staleness(&mut orphans);
+
+		assert_eq!(
+			orphans
+				.iter()
+				.map(|orphan| orphan.identifier.as_str())
+				.collect::<Vec<_>>(),
+			vec!["kitty", "alacritty", "wikipedia"]
+		);
+		assert_eq!(orphans[0].open_issues, Some(2));
+		assert_eq!(orphans[0].past_maintainers, vec!["someone"]);
+		assert!(orphans[2].userstyle && orphans[2].last_pushed.is_none());
+	}
+}
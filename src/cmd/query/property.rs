@@ -0,0 +1,152 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::Result;
+use serde::Serialize;
+
+use crate::cache::Cache;
+use crate::cli::{Format, PropertyQuery};
+use crate::format::{display, display_value};
+use crate::github::{fetch_all_repositories, fetch_properties, fetch_property, Github};
+use crate::token;
+
+/// Stands in for the value of repositories that don't have the property set.
+pub const UNSET: &str = "unset";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValueCount {
+	pub value: String,
+	pub repositories: usize,
+}
+
+/// Display the value of a custom property for one repository, or the values across the organization.
+///
+/// # Errors
+///
+/// Returns an error if no GitHub token is available or the values can't be fetched.
+pub fn handle(
+	cache: &mut Cache,
+	github: &Github,
+	query: PropertyQuery,
+	format: Format,
+) -> Result<()> {
+	let token = token::require(query.token, github, cache)?;
+	if let Some(repository) = query.r#for {
+		let value = fetch_property(cache, github, &repository, &query.name, token)?;
+
+		if let Some(is) = query.is {
+			display_value(self::is(value.as_deref(), &is) != query.not, format)
+		} else {
+			display_value(value, format)
+		}
+	} else {
+		let values = values(cache, github, &query.name, &token)?;
+
+		if let Some(is) = query.is {
+			display(matching(&values, &is, query.not), query.count, format)
+		} else {
+			display(tally(&values), query.count, format)
+		}
+	}
+}
+
+/// Values of a custom property for every non-archived repository in the organization, sorted by repository name.
+///
+/// # Errors
+///
+/// Returns an error if the repositories or their properties can't be fetched.
+pub fn values(
+	cache: &mut Cache,
+	github: &Github,
+	property: &str,
+	token: &str,
+) -> Result<Vec<(String, Option<String>)>> {
+	let repositories = fetch_all_repositories(cache, github, token)?;
+	let names = repositories
+		.iter()
+		.flatten()
+		.filter(|repo| !repo.is_archived)
+		.map(|repo| repo.name.as_str())
+		.collect::<Vec<_>>();
+
+	let mut values = fetch_properties(cache, github, &names, property, token)?
+		.into_iter()
+		.collect::<Vec<_>>();
+	values.sort();
+	Ok(values)
+}
+
+/// Whether a value is the given one, where [`UNSET`] matches repositories without the property.
+#[must_use]
+pub fn is(value: Option<&str>, expected: &str) -> bool {
+	value.unwrap_or(UNSET) == expected
+}
+
+/// Distinct values and how many repositories have each, most common first.
+#[must_use]
+pub fn tally(values: &[(String, Option<String>)]) -> Vec<ValueCount> {
+	let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+	for (_, value) in values {
+		*counts.entry(value.as_deref().unwrap_or(UNSET)).or_default() += 1;
+	}
+
+	let mut counts = counts
+		.into_iter()
+		.map(|(value, repositories)| ValueCount {
+			value: value.to_string(),
+			repositories,
+		})
+		.collect::<Vec<_>>();
+	counts.sort_by_key(|count| Reverse(count.repositories));
+	counts
+}
+
+/// Names of the repositories whose value is (or with `not`, isn't) the given one.
+#[must_use]
+pub fn matching<'a>(
+	values: &'a [(String, Option<String>)],
+	expected: &str,
+	not: bool,
+) -> Vec<&'a str> {
+	values
+		.iter()
+		.filter(|(_, value)| is(value.as_deref(), expected) != not)
+		.map(|(repository, _)| repository.as_str())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{matching, tally, ValueCount};
+
+	#[test]
+	fn summarize_values() {
+		let values = vec![
+			("bat".to_string(), Some("true".to_string())),
+			("nvim".to_string(), Some("true".to_string())),
+			("palette".to_string(), None),
+			("vscode".to_string(), Some("false".to_string())),
+		];
+
+		assert_eq!(
+			tally(&values),
+			vec![
+				ValueCount {
+					value: "true".to_string(),
+					repositories: 2
+				},
+				ValueCount {
+					value: "false".to_string(),
+					repositories: 1
+				},
+				ValueCount {
+					value: "unset".to_string(),
+					repositories: 1
+				},
+			]
+		);
+		assert_eq!(matching(&values, "true", false), vec!["bat", "nvim"]);
+		assert_eq!(matching(&values, "unset", false), vec!["palette"]);
+		assert_eq!(matching(&values, "true", true), vec!["palette", "vscode"]);
+	}
+}
@@ -0,0 +1,448 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use color_eyre::eyre::{bail, Context, Result};
+use etcetera::{choose_base_strategy, BaseStrategy};
+use serde::{Deserialize, Serialize};
+
+use crate::cache::Cache;
+use crate::cli::{QueryOptions, StarsQuery};
+use crate::format::{display, display_value};
+use crate::github::{all_repositories_fetched_at, fetch_all_repositories, fetch_stargazers};
+use crate::sources::Sources;
+use crate::token;
+use crate::utils::format_timestamp;
+
+/// Star counts of every repository at one point in time.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+	/// ISO 8601 timestamp in UTC, e.g. `2024-01-01T00:00:00Z`.
+	pub timestamp: String,
+	pub stars: BTreeMap<String, i64>,
+}
+
+impl Snapshot {
+	#[must_use]
+	pub fn total(&self) -> i64 {
+		self.stars.values().sum()
+	}
+
+	/// Rank the repositories by their star count (most first, ties by name), keeping at most `top` of them.
+	#[must_use]
+	pub fn ranking(&self, top: Option<usize>) -> Vec<Rank> {
+		let mut ranking = self
+			.stars
+			.iter()
+			.map(|(repository, &stars)| (repository, stars))
+			.collect::<Vec<_>>();
+		ranking.sort_by_key(|&(repository, stars)| (Reverse(stars), repository));
+
+		ranking
+			.into_iter()
+			.take(top.unwrap_or(usize::MAX))
+			.enumerate()
+			.map(|(index, (repository, stars))| Rank {
+				rank: index + 1,
+				repository: repository.clone(),
+				stars,
+			})
+			.collect()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Rank {
+	pub rank: usize,
+	pub repository: String,
+	pub stars: i64,
+}
+
+/// Snapshots of star counts, oldest first, kept in the platform data directory so that they outlive the cache.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct History {
+	pub snapshots: Vec<Snapshot>,
+}
+
+impl History {
+	/// # Panics
+	///
+	/// Panics if the home directory can't be determined.
+	#[must_use]
+	pub fn default_path() -> PathBuf {
+		choose_base_strategy()
+			.unwrap()
+			.data_dir()
+			.join("purr/stars.json")
+	}
+
+	/// Load the history at the given path, which is empty if the file does not exist yet.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file can't be read or parsed.
+	pub fn load(path: &Path) -> Result<Self> {
+		match fs::read_to_string(path) {
+			Ok(contents) => serde_json::from_str(&contents)
+				.with_context(|| format!("Failed to parse star history '{}'", path.display())),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(History::default()),
+			Err(err) => Err(err)
+				.with_context(|| format!("Failed to read star history '{}'", path.display())),
+		}
+	}
+
+	/// Apply a change to the history at the given path and write it back.
+	///
+	/// Like the cache store, the history is read again while holding an exclusive lock, the change is applied on top of it and the result is written to a temporary file that then replaces the history. It is therefore never left half written, and concurrent runs don't lose each other's snapshots.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the history can't be read, parsed or written.
+	pub fn update(path: &Path, change: impl FnOnce(&mut Self)) -> Result<()> {
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		let lock = fs::File::create(path.with_extension("json.lock"))
+			.context("Failed to create star history lock file")?;
+		lock.lock().context("Failed to lock star history")?;
+
+		let result = Self::load(path).and_then(|mut history| {
+			change(&mut history);
+			history.save_to_file(path)
+		});
+		lock.unlock().context("Failed to unlock star history")?;
+		result
+	}
+
+	/// Write the history to a temporary file next to it, then rename it over the history.
+	fn save_to_file(&self, path: &Path) -> Result<()> {
+		let temporary = path.with_extension(format!("json.{}.tmp", std::process::id()));
+		fs::write(&temporary, serde_json::to_string_pretty(self)?)?;
+		fs::rename(&temporary, path)
+			.inspect_err(|_| {
+				let _ = fs::remove_file(&temporary);
+			})
+			.with_context(|| format!("Failed to write star history '{}'", path.display()))
+	}
+
+	/// Add a snapshot, keeping the snapshots in chronological order.
+	pub fn record(&mut self, snapshot: Snapshot) {
+		let index = self
+			.snapshots
+			.partition_point(|s| s.timestamp <= snapshot.timestamp);
+		self.snapshots.insert(index, snapshot);
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+	pub repository: String,
+	pub from: i64,
+	pub to: i64,
+	pub change: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Point {
+	pub timestamp: String,
+	pub stars: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Trend {
+	pub since: String,
+	pub until: String,
+	pub total: Change,
+	pub gainers: Vec<Change>,
+	pub losers: Vec<Change>,
+	pub series: Vec<Point>,
+}
+
+/// Display the star count of one repository, or the star counts of the organization's repositories as a total, a ranking or a trend, or record them in the star history.
+///
+/// # Errors
+///
+/// Returns an error if no GitHub token is available, the star counts can't be fetched or the star history can't be read or written.
+pub fn handle(
+	cache: &mut Cache,
+	sources: &Sources,
+	query: StarsQuery,
+	options: &QueryOptions,
+) -> Result<()> {
+	let format = options.format;
+	let token = token::require(query.token, &sources.github, cache)?;
+	if let Some(repository) = query.r#for {
+		return display_value(
+			fetch_stargazers(cache, &sources.github, &repository, token)?,
+			format,
+		);
+	}
+
+	// Only repositories of ports matching the category and platform filters are counted, if any are given.
+	let ports = if query.categories.is_some() || query.platform.is_some() {
+		let ports = sources.ports(cache)?.ports;
+		Some(
+			ports
+				.into_iter()
+				.filter(|(_, port)| {
+					query.categories.as_ref().is_none_or(|categories| {
+						categories.iter().all(|c| port.categories.contains(c))
+					}) && query
+						.platform
+						.as_ref()
+						.is_none_or(|platform| platform.iter().all(|p| port.platform.contains(p)))
+				})
+				.map(|(identifier, _)| identifier)
+				.collect::<HashSet<_>>(),
+		)
+	} else {
+		None
+	};
+	let matches = |repository: &str| {
+		ports
+			.as_ref()
+			.is_none_or(|ports| ports.contains(repository))
+	};
+
+	// Snapshots are recorded from current star counts, and every star count is stamped with when it was fetched rather than when it was read from the cache.
+	if query.snapshot {
+		if cache.is_offline() {
+			bail!(
+				"star snapshots need current star counts, so they can't be recorded while offline"
+			);
+		}
+		cache.set_refresh(true);
+	}
+	let repositories = fetch_all_repositories(cache, &sources.github, &token)?;
+	let fetched_at =
+		all_repositories_fetched_at(cache, &sources.github).unwrap_or_else(SystemTime::now);
+	let current = Snapshot {
+		timestamp: format_timestamp(fetched_at),
+		stars: repositories
+			.iter()
+			.flatten()
+			.filter(|r| (query.archived || !r.is_archived) && matches(&r.name))
+			.map(|r| (r.name.clone(), r.stargazer_count))
+			.collect(),
+	};
+
+	if query.list || query.top.is_some() {
+		display(current.ranking(query.top), false, format)
+	} else if query.snapshot {
+		History::update(&History::default_path(), |history| {
+			history.record(current.clone());
+		})?;
+		display_value(
+			serde_json::json!({
+				"timestamp": current.timestamp,
+				"repositories": current.stars.len(),
+				"stars": current.total(),
+			}),
+			format,
+		)
+	} else if let Some(since) = query.since {
+		let mut history = History::load(&History::default_path())?;
+		for snapshot in &mut history.snapshots {
+			snapshot.stars.retain(|repository, _| matches(repository));
+		}
+		let Some(trend) = trend(&history, &since, &current, options.limit.unwrap_or(10)) else {
+			bail!("no star history since {since}, record some with `purr query stars --snapshot`");
+		};
+		display_value(trend, format)
+	} else {
+		display_value(current.total(), format)
+	}
+}
+
+/// Compare the current star counts with the first snapshot taken on or after a date (`YYYY-MM-DD`), listing up to `top` repositories with the largest gains and losses. Returns `None` if there is no snapshot since then.
+#[must_use]
+pub fn trend(history: &History, since: &str, current: &Snapshot, top: usize) -> Option<Trend> {
+	let snapshots = history
+		.snapshots
+		.iter()
+		.filter(|snapshot| snapshot.timestamp.as_str() >= since)
+		.collect::<Vec<_>>();
+	let baseline = snapshots.first()?;
+
+	// Repositories created since the baseline count as gaining all of their stars.
+	let mut changes = current
+		.stars
+		.iter()
+		.map(|(repository, &to)| {
+			let from = baseline.stars.get(repository).copied().unwrap_or(0);
+			Change {
+				repository: repository.clone(),
+				from,
+				to,
+				change: to - from,
+			}
+		})
+		.filter(|change| change.change != 0)
+		.collect::<Vec<_>>();
+	changes.sort_by_key(|change| (Reverse(change.change), change.repository.clone()));
+
+	let gainers = changes
+		.iter()
+		.filter(|change| change.change > 0)
+		.take(top)
+		.cloned()
+		.collect();
+	let losers = changes
+		.iter()
+		.rev()
+		.filter(|change| change.change < 0)
+		.take(top)
+		.cloned()
+		.collect();
+
+	let series = snapshots
+		.iter()
+		.copied()
+		.chain(Some(current).filter(|current| {
+			snapshots
+				.last()
+				.is_none_or(|last| last.timestamp < current.timestamp)
+		}))
+		.map(|snapshot| Point {
+			timestamp: snapshot.timestamp.clone(),
+			stars: snapshot.total(),
+		})
+		.collect();
+
+	Some(Trend {
+		since: baseline.timestamp.clone(),
+		until: current.timestamp.clone(),
+		total: Change {
+			repository: "*".to_string(),
+			from: baseline.total(),
+			to: current.total(),
+			change: current.total() - baseline.total(),
+		},
+		gainers,
+		losers,
+		series,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, UNIX_EPOCH};
+
+	use super::{trend, History, Rank, Snapshot};
+	use crate::utils::format_timestamp;
+
+	fn snapshot(timestamp: &str, stars: &[(&str, i64)]) -> Snapshot {
+		Snapshot {
+			timestamp: timestamp.to_string(),
+			stars: stars
+				.iter()
+				.map(|&(repository, count)| (repository.to_string(), count))
+				.collect(),
+		}
+	}
+
+	#[test]
+	fn format_timestamps() {
+		assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+		assert_eq!(
+			format_timestamp(UNIX_EPOCH + Duration::from_secs(1_709_210_096)),
+			"2024-02-29T12:34:56Z"
+		);
+	}
+
+	#[test]
+	fn rank_repositories() {
+		let current = snapshot(
+			"2024-01-01T00:00:00Z",
+			&[("bat", 50), ("nvim", 90), ("kitty", 50)],
+		);
+		let rank = |rank, repository: &str, stars| Rank {
+			rank,
+			repository: repository.to_string(),
+			stars,
+		};
+
+		assert_eq!(
+			current.ranking(None),
+			vec![
+				rank(1, "nvim", 90),
+				rank(2, "bat", 50),
+				rank(3, "kitty", 50)
+			]
+		);
+		assert_eq!(current.ranking(Some(1)), vec![rank(1, "nvim", 90)]);
+	}
+
+	#[test]
+	fn compute_trends() {
+		let mut history = History::default();
+		history.record(snapshot(
+			"2024-02-01T00:00:00Z",
+			&[("nvim", 100), ("bat", 50), ("kitty", 30)],
+		));
+		history.record(snapshot(
+			"2024-01-01T00:00:00Z",
+			&[("nvim", 90), ("bat", 50)],
+		));
+		history.record(snapshot(
+			"2024-03-01T00:00:00Z",
+			&[("nvim", 120), ("bat", 45), ("kitty", 30)],
+		));
+		let current = snapshot(
+			"2024-04-01T00:00:00Z",
+			&[("nvim", 130), ("bat", 40), ("kitty", 30), ("helix", 5)],
+		);
+
+		let trend = trend(&history, "2024-02-01", &current, 1).unwrap();
+		assert_eq!(trend.since, "2024-02-01T00:00:00Z");
+		assert_eq!((trend.total.from, trend.total.to), (180, 205));
+		assert_eq!(trend.gainers.len(), 1);
+		assert_eq!(trend.gainers[0].repository, "nvim");
+		assert_eq!(trend.gainers[0].change, 30);
+		assert_eq!(trend.losers[0].repository, "bat");
+		assert_eq!(trend.losers[0].change, -10);
+		assert_eq!(
+			trend.series.iter().map(|p| p.stars).collect::<Vec<_>>(),
+			vec![180, 195, 205]
+		);
+
+		assert!(super::trend(&history, "2025-01-01", &current, 1).is_none());
+	}
+
+	#[test]
+	fn keep_concurrent_snapshots() {
+		let path = std::env::temp_dir().join("purr-tests/stars/stars.json");
+		let _ = std::fs::remove_file(&path);
+
+		let threads = (1..=8)
+			.map(|day| {
+				let path = path.clone();
+				std::thread::spawn(move || {
+					History::update(&path, |history| {
+						history.record(snapshot(
+							&format!("2024-01-0{day}T00:00:00Z"),
+							&[("nvim", day)],
+						));
+					})
+					.unwrap();
+				})
+			})
+			.collect::<Vec<_>>();
+		for thread in threads {
+			thread.join().unwrap();
+		}
+
+		let history = History::load(&path).unwrap();
+		assert_eq!(
+			history
+				.snapshots
+				.iter()
+				.map(|s| s.stars["nvim"])
+				.collect::<Vec<_>>(),
+			(1..=8).collect::<Vec<_>>()
+		);
+	}
+}
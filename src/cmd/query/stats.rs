@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::cli::{QueryOptions, StatsDimension};
+use crate::format::{display, display_value};
+use crate::models::ports::Port;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Summary {
+	pub ports: usize,
+	pub upstreamed: usize,
+	pub unmaintained: usize,
+	pub categories: usize,
+	pub platforms: usize,
+	pub maintainers: usize,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+struct Counts {
+	ports: usize,
+	upstreamed: usize,
+	unmaintained: usize,
+	past: usize,
+}
+
+impl Counts {
+	fn add(&mut self, port: &Port) {
+		self.ports += 1;
+		if port.upstreamed.unwrap_or(false) {
+			self.upstreamed += 1;
+		}
+		if port.current_maintainers.is_empty() {
+			self.unmaintained += 1;
+		}
+	}
+}
+
+#[must_use]
+pub fn summary(ports: &[(String, Port)]) -> Summary {
+	let mut counts = Counts::default();
+	let mut categories = BTreeSet::new();
+	let mut platforms = BTreeSet::new();
+	let mut maintainers = BTreeSet::new();
+
+	for (_, port) in ports {
+		counts.add(port);
+		categories.extend(&port.categories);
+		platforms.extend(&port.platform);
+		maintainers.extend(port.current_maintainers.iter().map(|m| m.to_lowercase()));
+	}
+
+	Summary {
+		ports: counts.ports,
+		upstreamed: counts.upstreamed,
+		unmaintained: counts.unmaintained,
+		categories: categories.len(),
+		platforms: platforms.len(),
+		maintainers: maintainers.len(),
+	}
+}
+
+/// Display the summary of the ports, or their counts per value of a dimension.
+///
+/// # Errors
+///
+/// Returns an error if the statistics can't be displayed in the requested format.
+pub fn handle(
+	ports: &[(String, Port)],
+	by: Option<StatsDimension>,
+	count: bool,
+	options: &QueryOptions,
+) -> Result<()> {
+	if let Some(dimension) = by {
+		display(
+			self::by(ports, dimension)
+				.into_iter()
+				.take(options.limit.unwrap_or(usize::MAX))
+				.collect(),
+			count,
+			options.format,
+		)
+	} else {
+		display_value(summary(ports), options.format)
+	}
+}
+
+/// Aggregate counts per value of the given dimension, ordered by the number of ports (descending) and then by name.
+#[must_use]
+pub fn by(ports: &[(String, Port)], dimension: StatsDimension) -> Vec<Value> {
+	let mut groups: BTreeMap<String, Counts> = BTreeMap::new();
+
+	for (_, port) in ports {
+		match dimension {
+			StatsDimension::Category => {
+				for category in &port.categories {
+					groups.entry(category.clone()).or_default().add(port);
+				}
+			}
+			StatsDimension::Platform => {
+				for platform in &port.platform {
+					groups.entry(platform.clone()).or_default().add(port);
+				}
+			}
+			// Logins are case-insensitive, so maintainers are grouped in lowercase like in the summary.
+			StatsDimension::Maintainer => {
+				for maintainer in &port.current_maintainers {
+					groups.entry(maintainer.to_lowercase()).or_default().ports += 1;
+				}
+				for maintainer in port.past_maintainers.iter().flatten() {
+					groups.entry(maintainer.to_lowercase()).or_default().past += 1;
+				}
+			}
+		}
+	}
+
+	let mut groups = groups.into_iter().collect::<Vec<_>>();
+	groups.sort_by(|a, b| b.1.ports.cmp(&a.1.ports).then_with(|| a.0.cmp(&b.0)));
+
+	let key = dimension.to_string();
+	groups
+		.into_iter()
+		.map(|(name, counts)| match dimension {
+			StatsDimension::Maintainer => json!({
+				key.as_str(): name,
+				"ports": counts.ports,
+				"past": counts.past,
+			}),
+			_ => json!({
+				key.as_str(): name,
+				"ports": counts.ports,
+				"upstreamed": counts.upstreamed,
+				"unmaintained": counts.unmaintained,
+			}),
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::{by, summary, Summary};
+	use crate::cli::StatsDimension;
+	use crate::cmd::query::load_data;
+	use crate::cmd::query::tests::fixtures;
+
+	#[test]
+	fn compute_stats() {
+		let (mut cache, sources) = fixtures();
+		let ports = load_data(&mut cache, &sources, true, false).unwrap();
+
+		assert_eq!(
+			summary(&ports),
+			Summary {
+				ports: 6,
+				upstreamed: 1,
+				unmaintained: 2,
+				categories: 6,
+				platforms: 4,
+				maintainers: 3,
+			}
+		);
+		assert_eq!(
+			by(&ports, StatsDimension::Category)[0],
+			json!({ "category": "development", "ports": 3, "upstreamed": 1, "unmaintained": 0 })
+		);
+		assert_eq!(
+			by(&ports, StatsDimension::Maintainer).last(),
+			Some(&json!({ "maintainer": "pocco81", "ports": 0, "past": 2 }))
+		);
+
+		// The same login in another case is still the same maintainer.
+		let mut ports = ports;
+		let (_, bat) = ports.iter_mut().find(|(id, _)| id == "bat").unwrap();
+		bat.current_maintainers = vec!["SGoudham".to_string()];
+		assert_eq!(summary(&ports).maintainers, 3);
+		assert_eq!(
+			by(&ports, StatsDimension::Maintainer)[0],
+			json!({ "maintainer": "sgoudham", "ports": 2, "past": 0 })
+		);
+	}
+}
@@ -1,7 +1,8 @@
-use color_eyre::eyre::{Context, Result};
+use std::cmp::Ordering;
+
 use serde_json::Value;
 
-use crate::{cli::Key, models::ports::Port};
+use crate::{cli::Key, github::Repository, models::ports::Port, utils::suggest};
 
 pub fn matches_current_maintainer(current_maintainers: &[String], by: &Option<String>) -> bool {
 	match &by {
@@ -12,17 +13,53 @@ pub fn matches_current_maintainer(current_maintainers: &[String], by: &Option<St
 	}
 }
 
-pub fn display_json_or_count<T: serde::Serialize>(result: Vec<T>, count: bool) -> Result<()> {
-	println!(
-		"{}",
-		if count {
-			result.len().to_string()
-		} else {
-			serde_json::to_string_pretty(&result).context("Failed to serialize results")?
-		}
-	);
+/// Find the port referred to by name, preferring an identifier match over a display name match over an alias match (all case-insensitive).
+pub fn find_port<'a>(ports: &'a [(String, Port)], name: &str) -> Option<&'a (String, Port)> {
+	let name = name.to_lowercase();
+	ports
+		.iter()
+		.find(|(identifier, _)| identifier.to_lowercase() == name)
+		.or_else(|| {
+			ports
+				.iter()
+				.find(|(_, port)| port.name.to_lowercase() == name)
+		})
+		.or_else(|| {
+			ports.iter().find(|(_, port)| {
+				port.alias
+					.as_ref()
+					.is_some_and(|alias| alias.to_lowercase() == name)
+			})
+		})
+}
 
-	Ok(())
+/// Whether a name refers to the port by its identifier, display name or alias (case-insensitively).
+pub fn matches_port_name((identifier, port): &(String, Port), name: &str) -> bool {
+	let name = name.to_lowercase();
+	identifier.to_lowercase() == name
+		|| port.name.to_lowercase() == name
+		|| port
+			.alias
+			.as_ref()
+			.is_some_and(|alias| alias.to_lowercase() == name)
+}
+
+/// How closely a name matches the port's identifier, display name or alias, if it is a likely match at all.
+pub fn port_similarity((identifier, port): &(String, Port), name: &str) -> Option<f64> {
+	let candidates = [Some(identifier), Some(&port.name), port.alias.as_ref()];
+	suggest(name, candidates.into_iter().flatten().map(String::as_str))
+		.first()
+		.map(|(_, confidence)| *confidence)
+}
+
+/// Ports that likely match a name, best match first.
+pub fn closest_ports<'a>(ports: &'a [(String, Port)], name: &str) -> Vec<&'a (String, Port)> {
+	let mut matches = ports
+		.iter()
+		.filter_map(|port| port_similarity(port, name).map(|confidence| (port, confidence)))
+		.collect::<Vec<_>>();
+	matches.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+	matches.into_iter().map(|(port, _)| port).collect()
 }
 
 pub fn is_booleanish_match(value: Option<String>, expected: &str) -> bool {
@@ -35,7 +72,12 @@ pub fn is_booleanish_match(value: Option<String>, expected: &str) -> bool {
 		})
 }
 
-pub fn get_key((identifier, port): (String, Port), key: Key) -> Value {
+/// The value of a property of a port, where repository properties come from the port's repository (if it has one).
+pub fn get_key(
+	(identifier, port): (String, Port),
+	key: Key,
+	repository: Option<&Repository>,
+) -> Value {
 	fn optional_string(value: Option<String>) -> Value {
 		value.map_or(Value::Null, Value::String)
 	}
@@ -61,5 +103,72 @@ pub fn get_key((identifier, port): (String, Port), key: Key) -> Value {
 				.map(Value::String)
 				.collect(),
 		),
+		_ => repository.cloned().map_or(Value::Null, |repository| {
+			get_repository_key(repository, key)
+		}),
+	}
+}
+
+fn get_repository_key(repository: Repository, key: Key) -> Value {
+	match key {
+		Key::Description => repository.description.map_or(Value::Null, Value::String),
+		Key::Topics => Value::Array(
+			repository
+				.repository_topics
+				.nodes
+				.into_iter()
+				.flatten()
+				.flatten()
+				.map(|node| Value::String(node.topic.name))
+				.collect(),
+		),
+		Key::Language => repository
+			.primary_language
+			.map_or(Value::Null, |language| Value::String(language.name)),
+		Key::PushedAt => repository.pushed_at.map_or(Value::Null, Value::String),
+		Key::OpenIssues => Value::from(repository.issues.total_count),
+		Key::OpenPullRequests => Value::from(repository.pull_requests.total_count),
+		Key::DefaultBranch => repository
+			.default_branch_ref
+			.map_or(Value::Null, |branch| Value::String(branch.name)),
+		Key::License => repository
+			.license_info
+			.and_then(|license| license.spdx_id)
+			.map_or(Value::Null, Value::String),
+		Key::Homepage => repository
+			.homepage_url
+			.filter(|url| !url.is_empty())
+			.map_or(Value::Null, Value::String),
+		_ => Value::Null,
+	}
+}
+
+/// Total ordering over property values for sorting: null, then booleans, numbers, strings and finally lists (compared item by item).
+pub fn compare_values(a: &Value, b: &Value) -> Ordering {
+	fn rank(value: &Value) -> u8 {
+		match value {
+			Value::Null => 0,
+			Value::Bool(_) => 1,
+			Value::Number(_) => 2,
+			Value::String(_) => 3,
+			Value::Array(_) => 4,
+			Value::Object(_) => 5,
+		}
+	}
+
+	match (a, b) {
+		(Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+		(Value::Number(a), Value::Number(b)) => a
+			.as_f64()
+			.partial_cmp(&b.as_f64())
+			.unwrap_or(Ordering::Equal),
+		(Value::String(a), Value::String(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+		(Value::Array(a), Value::Array(b)) => a
+			.iter()
+			.zip(b)
+			.map(|(a, b)| compare_values(a, b))
+			.find(|ordering| ordering.is_ne())
+			.unwrap_or_else(|| a.len().cmp(&b.len())),
+		_ => rank(a).cmp(&rank(b)),
 	}
 }
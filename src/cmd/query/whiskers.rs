@@ -0,0 +1,327 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::cache::Cache;
+use crate::cli::{Format, WhiskersQuery};
+use crate::format::{display_value, render};
+use crate::github::{
+	fetch_all_repositories, fetch_has_whiskers_template, fetch_whiskers_status, Github,
+};
+use crate::models::ports::Port;
+use crate::sources::Sources;
+use crate::token;
+
+use super::property;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Progress {
+	pub migrated: usize,
+	pub not_migrated: usize,
+	/// Share of the repositories Whiskers applies to that are migrated.
+	pub percentage: f64,
+}
+
+impl Progress {
+	fn new(migrated: usize, not_migrated: usize) -> Self {
+		let total = migrated + not_migrated;
+		#[allow(clippy::cast_precision_loss)]
+		let percentage = if total == 0 {
+			0.0
+		} else {
+			(migrated as f64 / total as f64 * 10000.0).round() / 100.0
+		};
+		Progress {
+			migrated,
+			not_migrated,
+			percentage,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Summary {
+	#[serde(flatten)]
+	pub progress: Progress,
+	pub not_applicable: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Category {
+	pub category: String,
+	#[serde(flatten)]
+	pub progress: Progress,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Pending {
+	pub repository: String,
+	pub maintainers: Vec<String>,
+}
+
+/// The state of the Whiskers migration across the organization.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Dashboard {
+	pub summary: Summary,
+	/// Progress of the ports in each category, least migrated first.
+	pub categories: Vec<Category>,
+	/// Repositories that aren't migrated yet, with the current maintainers of their port.
+	pub pending: Vec<Pending>,
+	/// Repositories marked as migrated without a `.tera` file on their default branch, if that was checked.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub missing_templates: Option<Vec<String>>,
+}
+
+/// Build the dashboard from the `whiskers` property of each repository, joining repositories with their port in ports.yml by identifier. Repositories that aren't ports only count towards the summary.
+#[must_use]
+pub fn dashboard<S: BuildHasher>(
+	values: &[(String, Option<String>)],
+	ports: &HashMap<String, Port, S>,
+	missing_templates: Option<Vec<String>>,
+) -> Dashboard {
+	let (mut migrated, mut not_migrated) = (0, 0);
+	let mut categories: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+	let mut pending = vec![];
+	for (repository, value) in values {
+		// Repositories Whiskers doesn't apply to (or without the property) only count as not applicable.
+		let migrated_here = match value.as_deref() {
+			Some("true") => true,
+			Some("false") => false,
+			_ => continue,
+		};
+		if migrated_here {
+			migrated += 1;
+		} else {
+			not_migrated += 1;
+		}
+
+		let port = ports.get(repository);
+		for category in port.iter().flat_map(|port| &port.categories) {
+			let counts = categories.entry(category).or_default();
+			if migrated_here {
+				counts.0 += 1;
+			} else {
+				counts.1 += 1;
+			}
+		}
+		if !migrated_here {
+			pending.push(Pending {
+				repository: repository.clone(),
+				maintainers: port
+					.map(|port| port.current_maintainers.clone())
+					.unwrap_or_default(),
+			});
+		}
+	}
+
+	let mut categories = categories
+		.into_iter()
+		.map(|(category, (migrated, not_migrated))| Category {
+			category: category.to_string(),
+			progress: Progress::new(migrated, not_migrated),
+		})
+		.collect::<Vec<_>>();
+	categories.sort_by(|a, b| a.progress.percentage.total_cmp(&b.progress.percentage));
+
+	Dashboard {
+		summary: Summary {
+			progress: Progress::new(migrated, not_migrated),
+			not_applicable: values.len() - migrated - not_migrated,
+		},
+		categories,
+		pending,
+		missing_templates,
+	}
+}
+
+/// Display the Whiskers state of one repository, the repositories in a given state or the migration dashboard.
+///
+/// # Errors
+///
+/// Returns an error if no GitHub token is available or the data can't be fetched.
+pub fn handle(
+	cache: &mut Cache,
+	sources: &Sources,
+	query: WhiskersQuery,
+	format: Format,
+) -> Result<()> {
+	let token = token::require(query.token, &sources.github, cache)?;
+	if let Some(repository) = query.r#for {
+		let status = fetch_whiskers_status(cache, &sources.github, &repository, token)?;
+
+		return if let Some(is) = query.is {
+			let matches = status == is.to_string();
+			display_value(matches != query.not, format)
+		} else {
+			display_value(status, format)
+		};
+	}
+
+	let values = property::values(cache, &sources.github, "whiskers", &token)?;
+	if let Some(is) = query.is {
+		return crate::format::display(
+			property::matching(&values, &is.to_string(), query.not),
+			query.count,
+			format,
+		);
+	}
+
+	let missing_templates = if query.check_templates {
+		Some(missing_templates(cache, &sources.github, &values, &token)?)
+	} else {
+		None
+	};
+	let ports = sources.ports(cache)?.ports;
+	display(&dashboard(&values, &ports, missing_templates), format)
+}
+
+/// Migrated repositories without a .tera template on their default branch.
+fn missing_templates(
+	cache: &mut Cache,
+	github: &Github,
+	values: &[(String, Option<String>)],
+	token: &str,
+) -> Result<Vec<String>> {
+	let branches = fetch_all_repositories(cache, github, token)?
+		.into_iter()
+		.flatten()
+		.filter_map(|r| Some((r.name, r.default_branch_ref?.name)))
+		.collect::<HashMap<_, _>>();
+
+	let mut missing = vec![];
+	for (repository, value) in values {
+		if !property::is(value.as_deref(), "true") {
+			continue;
+		}
+		// Repositories without a default branch (e.g. empty ones) have no template either.
+		let has_template = match branches.get(repository) {
+			Some(branch) => fetch_has_whiskers_template(cache, github, repository, branch, token)?,
+			None => false,
+		};
+		if !has_template {
+			missing.push(repository.clone());
+		}
+	}
+	Ok(missing)
+}
+
+/// Print the dashboard. The nested formats print it as a whole and tables print each part as its own titled table, while CSV and TSV print a single table with the part of each row in a `section` column, so that the output stays valid.
+///
+/// # Errors
+///
+/// Returns an error if the dashboard can't be serialized in the requested format.
+pub fn display(dashboard: &Dashboard, format: Format) -> Result<()> {
+	if matches!(format, Format::Json | Format::Ndjson | Format::Yaml) {
+		return display_value(dashboard, format);
+	}
+
+	let rows = |value: Value| value.as_array().cloned().unwrap_or_default();
+	let mut parts = vec![
+		(
+			"Summary",
+			"summary",
+			vec![serde_json::to_value(&dashboard.summary)?],
+		),
+		(
+			"Categories",
+			"category",
+			rows(serde_json::to_value(&dashboard.categories)?),
+		),
+		(
+			"Not migrated",
+			"not-migrated",
+			rows(serde_json::to_value(&dashboard.pending)?),
+		),
+	];
+	if let Some(missing) = &dashboard.missing_templates {
+		parts.push((
+			"Migrated without a template",
+			"missing-template",
+			missing
+				.iter()
+				.map(|repository| serde_json::json!({ "repository": repository }))
+				.collect::<Vec<Value>>(),
+		));
+	}
+
+	if format == Format::Table {
+		let sections = parts
+			.into_iter()
+			.map(|(title, _, rows)| Ok(format!("{title}\n{}", render(&rows, format)?)))
+			.collect::<Result<Vec<_>>>()?;
+		print!("{}", sections.join("\n"));
+		return Ok(());
+	}
+
+	let mut flat = vec![];
+	for (_, section, rows) in parts {
+		for row in rows {
+			let mut fields = Map::new();
+			fields.insert("section".to_string(), Value::String(section.to_string()));
+			if let Value::Object(row) = row {
+				fields.extend(row);
+			}
+			flat.push(Value::Object(fields));
+		}
+	}
+	print!("{}", render(&flat, format)?);
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use super::{dashboard, Pending};
+	use crate::models::ports::Port;
+
+	#[test]
+	fn summarize_migration() {
+		let port = |categories: &[&str], maintainers: &[&str]| Port {
+			categories: categories.iter().map(ToString::to_string).collect(),
+			current_maintainers: maintainers.iter().map(ToString::to_string).collect(),
+			..Default::default()
+		};
+		let ports = HashMap::from([
+			("nvim".to_string(), port(&["code_editor"], &["mrtnvgr"])),
+			("helix".to_string(), port(&["code_editor"], &["rubyowo"])),
+			("bat".to_string(), port(&["cli"], &[])),
+		]);
+		let value = |repository: &str, value: Option<&str>| {
+			(repository.to_string(), value.map(ToString::to_string))
+		};
+		let values = vec![
+			value("bat", Some("true")),
+			value("helix", Some("false")),
+			value("nvim", Some("true")),
+			value("palette", Some("not_applicable")),
+			value("website", None),
+		];
+
+		let dashboard = dashboard(&values, &ports, Some(vec!["bat".to_string()]));
+		assert_eq!(dashboard.summary.progress.migrated, 2);
+		assert_eq!(dashboard.summary.progress.percentage, 66.67);
+		assert_eq!(dashboard.summary.not_applicable, 2);
+		assert_eq!(
+			dashboard
+				.categories
+				.iter()
+				.map(|c| (c.category.as_str(), c.progress.percentage))
+				.collect::<Vec<_>>(),
+			vec![("code_editor", 50.0), ("cli", 100.0)]
+		);
+		assert_eq!(
+			dashboard.pending,
+			vec![Pending {
+				repository: "helix".to_string(),
+				maintainers: vec!["rubyowo".to_string()],
+			}]
+		);
+	}
+}
@@ -1,18 +1,45 @@
 use log::warn;
+use std::ops::Range;
+use std::path::Path;
 use std::{fs, path::PathBuf};
 
 use color_eyre::{eyre::Result, owo_colors::OwoColorize};
-use fancy_regex::Regex;
+use fancy_regex::{Captures, Regex};
 
 use catppuccin::Hsl;
 
-pub fn handle(input: PathBuf, output: Option<PathBuf>) -> Result<()> {
-	let original: String = fs::read_to_string(&input)?;
-	let new = convert(original.clone(), Some(input));
+/// A color or flavor name in the original file and the Tera expression replacing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution {
+	pub line: usize,
+	pub column: usize,
+	pub original: String,
+	pub replacement: String,
+	range: Range<usize>,
+}
+
+pub fn handle(input: &Path, output: Option<PathBuf>, dry_run: bool) -> Result<()> {
+	let original: String = fs::read_to_string(input)?;
+	let substitutions = substitutions(&original, Some(input));
+
+	if dry_run {
+		for substitution in &substitutions {
+			println!(
+				"{}:{}:{}: {} -> {}",
+				input.display(),
+				substitution.line,
+				substitution.column,
+				substitution.original.yellow(),
+				substitution.replacement.green()
+			);
+		}
+		return Ok(());
+	}
 
-	if original == new {
+	if substitutions.is_empty() {
 		warn!("no changes made to original file");
 	}
+	let new = apply(&original, &substitutions);
 
 	if let Some(path) = output {
 		fs::write(path, new)?;
@@ -23,139 +50,326 @@ pub fn handle(input: PathBuf, output: Option<PathBuf>) -> Result<()> {
 	Ok(())
 }
 
-pub fn convert(mut contents: String, input_path: Option<PathBuf>) -> String {
-	let mut color_matches: Vec<(String, csscolorparser::Color)> = Regex::new("(rgb|hsl)a?\\(.*\\)")
-		.unwrap()
-		.captures_iter(&contents.clone())
-		.filter_map(|m| {
-			let text = m.unwrap().get(0).unwrap().as_str();
-			let color = match csscolorparser::parse(text) {
-				Ok(c) => c,
-				Err(_) => {
-					warn!("invalid color '{}'", text);
-					return None;
+#[must_use]
+pub fn convert(contents: &str, input_path: Option<&Path>) -> String {
+	apply(contents, &substitutions(contents, input_path))
+}
+
+/// Find the colors and flavor names to replace, in order. Only whole tokens are replaced, and never inside comments or in the key of a `key: value` or `key = value` line. Colors in `rgb()`/`hsl()` notation that aren't from the palette are warned about.
+#[must_use]
+pub fn substitutions(contents: &str, input_path: Option<&Path>) -> Vec<Substitution> {
+	let comments = comments(contents);
+	let substitution = |range: Range<usize>, replacement: String| {
+		let (line, column) = location(contents, range.start);
+		Substitution {
+			line,
+			column,
+			original: contents[range.clone()].to_string(),
+			replacement,
+			range,
+		}
+	};
+
+	let functions = Regex::new(r"(?i)(?<![\w-])(rgb|hsl)a?\([^)]*\)").unwrap();
+	let hex = Regex::new(
+		"(?i)(?<![0-9a-z_-])(#|0x)?([0-9a-f]{8}|[0-9a-f]{6}|[0-9a-f]{3,4})(?![0-9a-z_-])",
+	)
+	.unwrap();
+	let names = Regex::new(&format!(
+		r"(?<![\p{{L}}\p{{N}}])({})(?![\p{{L}}\p{{N}}])",
+		catppuccin::PALETTE
+			.all_flavors()
+			.iter()
+			.flat_map(|flavor| [flavor.name.to_string(), flavor.identifier().to_string()])
+			.map(|name| fancy_regex::escape(&name).into_owned())
+			.collect::<Vec<_>>()
+			.join("|")
+	))
+	.unwrap();
+
+	let mut found = vec![];
+	for captures in functions.captures_iter(contents).flatten() {
+		let text = captures.get(0).unwrap();
+		// Colors in comments are left alone, so they aren't warned about either.
+		if in_ranges(&comments, text.start()) {
+			continue;
+		}
+		let Some(expression) = function_expression(text.as_str()) else {
+			let (line, column) = location(contents, text.start());
+			warn!(
+				"could not replace non-Catppuccin color '{}'{}",
+				text.as_str().yellow(),
+				match input_path {
+					Some(p) => format!(" at {}:{line}:{column}", p.to_string_lossy()),
+					None => String::new(),
 				}
-			};
+			);
+			continue;
+		};
+		found.push(substitution(text.range(), as_tera_expr(&expression)));
+	}
+	for captures in hex.captures_iter(contents).flatten() {
+		if let Some(replacement) = hex_replacement(&captures) {
+			found.push(substitution(captures.get(0).unwrap().range(), replacement));
+		}
+	}
+	for name in names.find_iter(contents).flatten() {
+		let is_name = catppuccin::PALETTE
+			.all_flavors()
+			.iter()
+			.any(|flavor| flavor.name.to_string() == name.as_str());
+		let expression = if is_name {
+			"flavor.name"
+		} else {
+			"flavor.identifier"
+		};
+		found.push(substitution(name.range(), as_tera_expr(expression)));
+	}
 
-			Some((text.to_string(), color))
+	found.sort_by_key(|substitution| substitution.range.start);
+	let mut end = 0;
+	found
+		.into_iter()
+		.filter(|substitution| {
+			let keep = substitution.range.start >= end
+				&& !in_ranges(&comments, substitution.range.start)
+				&& !is_key(contents, &substitution.range);
+			if keep {
+				end = substitution.range.end;
+			}
+			keep
 		})
-		.collect::<Vec<_>>();
+		.collect()
+}
 
-	for flavor in catppuccin::PALETTE.all_flavors() {
-		contents = contents
-			.replace(&flavor.name.to_string(), &as_tera_expr("flavor.name"))
-			.replace(
-				&flavor.identifier().to_string(),
-				&as_tera_expr("flavor.identifier"),
-			);
+/// Replace the substitutions, which must be in order and not overlap.
+fn apply(contents: &str, substitutions: &[Substitution]) -> String {
+	let mut result = String::with_capacity(contents.len());
+	let mut end = 0;
+	for substitution in substitutions {
+		result.push_str(&contents[end..substitution.range.start]);
+		result.push_str(&substitution.replacement);
+		end = substitution.range.end;
+	}
+	result.push_str(&contents[end..]);
+	result
+}
 
+/// The expression for a color in `rgb()`/`hsl()` notation (or their `a` variants), if it is from the palette.
+fn function_expression(text: &str) -> Option<String> {
+	let Ok(parsed) = csscolorparser::parse(text) else {
+		warn!("invalid color '{}'", text);
+		return None;
+	};
+
+	for flavor in catppuccin::PALETTE.all_flavors() {
 		for color in &flavor.colors {
-			let pat = "(?i)".to_string() + &color.hex.to_string()[1..];
-			let search = Regex::new(&pat).unwrap();
+			let res = if text.to_lowercase().starts_with("hsl") {
+				let expected = hsl_to_vec(&color.hsl)
+					.into_iter()
+					.map(round_to_two_decimal_places)
+					.collect::<Vec<_>>();
 
-			for result in search.find_iter(&contents.clone()).flatten() {
-				contents = contents.replace(
-					result.as_str(),
-					&as_tera_expr(&(color.identifier().to_string() + ".hex")),
-				);
-			}
+				let mut values = <(f64, f64, f64, f64) as Into<[f64; 4]>>::into(parsed.to_hsla())
+					.into_iter()
+					.map(round_to_two_decimal_places)
+					.collect::<Vec<_>>();
+
+				// The alpha value is taken before rounding, so that it isn't rounded twice.
+				values.pop();
+				let opacity = (parsed.to_hsla().3 * 255.0).round() as u8;
+
+				let colors_match =
+					expected
+						.iter()
+						.zip(values.iter())
+						.all(|(&expected_val, &val)| {
+							let tolerance = if expected_val < 1.0 && val < 1.0 {
+								0.02
+							} else {
+								1.0
+							};
+							(expected_val - val).abs() < tolerance
+						});
+
+				(colors_match, opacity, "hsl")
+			} else {
+				let values = parsed.to_rgba8();
+				let colors_match = color.rgb.r == values[0]
+					&& color.rgb.g == values[1]
+					&& color.rgb.b == values[2];
+				(colors_match, values[3], "rgb")
+			};
 
-			for (text, color_match) in color_matches.clone() {
-				let res = if text.contains("hsl") {
-					let expected = hsl_to_vec(&color.hsl)
-						.into_iter()
-						.map(round_to_two_decimal_places)
-						.collect::<Vec<_>>();
-
-					let mut values =
-						<(f64, f64, f64, f64) as Into<[f64; 4]>>::into(color_match.to_hsla())
-							.into_iter()
-							.map(round_to_two_decimal_places)
-							.collect::<Vec<_>>();
-
-					let opacity = (values.pop().unwrap() * 255.0).round() as u8;
-
-					let colors_match =
-						expected
-							.iter()
-							.zip(values.iter())
-							.all(|(&expected_val, &val)| {
-								let tolerance = if expected_val < 1.0 && val < 1.0 {
-									0.02
-								} else {
-									1.0
-								};
-								(expected_val - val).abs() < tolerance
-							});
-
-					(colors_match, opacity, "hsl")
+			if res.0 {
+				let opacity = res.1;
+
+				let filters = if opacity == 255 {
+					format!(" | css_{}", res.2)
 				} else {
-					let values = color_match.to_rgba8();
-					let colors_match = color.rgb.r == values[0]
-						&& color.rgb.g == values[1]
-						&& color.rgb.b == values[2];
-					(colors_match, values[3], "rgb")
+					format!(" | mod(opacity={}) | css_{}a", opacity_of(opacity), res.2)
 				};
 
-				if res.0 {
-					let opacity = res.1;
-
-					let filters = if opacity == 255 {
-						format!(" | css_{}", res.2)
-					} else {
-						format!(
-							" | mod(opacity={:.2}) | css_{}a",
-							f32::from(opacity) / 255_f32,
-							res.2
-						)
-					};
-
-					contents = contents.replace(
-						&text,
-						&as_tera_expr(&(color.identifier().to_owned() + &filters)),
-					);
-					color_matches.retain(|x| *x.0 != *text);
-				}
+				return Some(color.identifier().to_owned() + &filters);
 			}
 		}
 	}
+	None
+}
+
+/// The replacement for a hex color from the palette: `#RRGGBB`, `#RRGGBBAA`, `#RGB`, `#RGBA`, `0xRRGGBB` or bare `RRGGBB` or `RRGGBBAA`. The prefix is kept as it is, and uppercase colors stay uppercase. Colors with an alpha channel become the color with that opacity, whose `hex` includes the alpha.
+fn hex_replacement(captures: &Captures) -> Option<String> {
+	let prefix = captures.get(1).map_or("", |prefix| prefix.as_str());
+	let digits = &captures[2];
+
+	// Short colors are only recognized after a `#`, and `0x` colors only without alpha.
+	let recognized = match prefix {
+		"#" => true,
+		"" => matches!(digits.len(), 6 | 8),
+		_ => digits.len() == 6,
+	};
+	if !recognized {
+		return None;
+	}
+	let ([r, g, b], alpha) = parse_hex(digits)?;
+	let color = catppuccin::PALETTE
+		.all_flavors()
+		.into_iter()
+		.flat_map(|flavor| &flavor.colors)
+		.find(|color| color.rgb.r == r && color.rgb.g == g && color.rgb.b == b)?;
+
+	let mut expression = if alpha == 255 {
+		color.identifier().to_string() + ".hex"
+	} else {
+		format!(
+			"{} | mod(opacity={}) | get(key=\"hex\")",
+			color.identifier(),
+			opacity_of(alpha)
+		)
+	};
+	let uppercase = digits.chars().any(|c| c.is_ascii_alphabetic())
+		&& !digits.chars().any(|c| c.is_ascii_lowercase());
+	if uppercase {
+		expression += " | upper";
+	}
+	Some(format!("{prefix}{}", as_tera_expr(&expression)))
+}
+
+/// The opacity of an alpha channel value, with two decimals if that is enough to get the same alpha value back (as `round(opacity * 255)`) and three otherwise, which always is.
+fn opacity_of(alpha: u8) -> String {
+	let opacity = f64::from(alpha) / 255.0;
+	let short = format!("{opacity:.2}");
+	if short
+		.parse::<f64>()
+		.is_ok_and(|short| ((short * 255.0).round() - f64::from(alpha)).abs() < 0.5)
+	{
+		short
+	} else {
+		format!("{opacity:.3}")
+	}
+}
 
-	for (text, _) in color_matches {
-		warn!(
-			"could not replace non-Catppuccin color '{}'{}",
-			text.yellow(),
-			match input_path {
-				Some(ref p) => format!(
-					" at {}:{}",
-					p.to_string_lossy(),
-					&get_location_in_text(&text, &contents),
-				),
-				None => String::new(),
+/// The RGB channels and alpha of hex digits in any of the forms `RRGGBB`, `RRGGBBAA`, `RGB` or `RGBA`.
+fn parse_hex(digits: &str) -> Option<([u8; 3], u8)> {
+	let digits = if digits.len() <= 4 {
+		digits.chars().flat_map(|c| [c, c]).collect::<String>()
+	} else {
+		digits.to_string()
+	};
+	let channels = (0..digits.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+		.collect::<Option<Vec<_>>>()?;
+
+	match channels[..] {
+		[r, g, b] => Some(([r, g, b], 255)),
+		[r, g, b, a] => Some(([r, g, b], a)),
+		_ => None,
+	}
+}
+
+/// Byte ranges of comments, recognizing `//`, `#`, `;` and `--` line comments and `/* */` and `<!-- -->` block comments outside of quoted strings. To tell comments apart from other uses of these characters, `#`, `;` and `--` must be followed by whitespace, and `//`, `#` and `;` must start a line or follow whitespace (unlike in URLs such as `url(//cdn.example.com)`).
+fn comments(contents: &str) -> Vec<Range<usize>> {
+	let mut comments = vec![];
+	let mut quote = None;
+	let mut chars = contents.char_indices().peekable();
+	let mut previous = '\n';
+
+	while let Some((i, c)) = chars.next() {
+		let rest = &contents[i..];
+		let followed_by_space = rest[c.len_utf8()..]
+			.chars()
+			.next()
+			.is_none_or(char::is_whitespace);
+
+		if let Some(q) = quote {
+			if c == q && previous != '\\' || c == '\n' {
+				quote = None;
 			}
-		);
+		} else if matches!(c, '"' | '\'') && !previous.is_alphanumeric() {
+			quote = Some(c);
+		} else if let Some((open, close)) = [("/*", "*/"), ("<!--", "-->")]
+			.into_iter()
+			.find(|(open, _)| rest.starts_with(open))
+		{
+			let end = rest[open.len()..]
+				.find(close)
+				.map_or(contents.len(), |end| i + open.len() + end + close.len());
+			comments.push(i..end);
+			while chars.peek().is_some_and(|&(j, _)| j < end) {
+				chars.next();
+			}
+			previous = ' ';
+			continue;
+		} else if (rest.starts_with("//") && previous.is_whitespace())
+			|| (matches!(c, '#' | ';') && previous.is_whitespace() && followed_by_space)
+			|| (rest.starts_with("--") && rest[2..].chars().next().is_none_or(char::is_whitespace))
+		{
+			let end = rest.find('\n').map_or(contents.len(), |end| i + end);
+			comments.push(i..end);
+			while chars.peek().is_some_and(|&(j, _)| j < end) {
+				chars.next();
+			}
+			previous = ' ';
+			continue;
+		}
+		previous = c;
 	}
 
-	contents
+	comments
 }
 
-fn as_tera_expr(value: &str) -> String {
-	format!("{} {} {}", "{{", value, "}}")
+fn in_ranges(ranges: &[Range<usize>], index: usize) -> bool {
+	ranges.iter().any(|range| range.contains(&index))
 }
 
-fn get_location_in_text(search: &str, text: &str) -> String {
-	let (line_number, line_content) = text
-		.lines()
-		.enumerate()
-		.find(|(_i, line)| line.contains(search))
-		.unwrap();
+/// Whether a token is the key of a `key: value` or `key = value` line (possibly quoted), rather than a value.
+fn is_key(contents: &str, range: &Range<usize>) -> bool {
+	let line_start = contents[..range.start].rfind('\n').map_or(0, |i| i + 1);
+	let before = contents[line_start..range.start].trim_start();
+	let after = contents[range.end..]
+		.trim_start_matches(['"', '\''])
+		.trim_start();
 
-	format!(
-		"{}:{}",
-		line_number + 1,
-		line_content.find(search).unwrap() + 1
+	matches!(before, "" | "\"" | "'")
+		&& (after.starts_with(':') && !after.starts_with("::")
+			|| after.starts_with('=') && !after.starts_with("=="))
+}
+
+/// The 1-based line and column (in characters) of a byte offset.
+fn location(contents: &str, index: usize) -> (usize, usize) {
+	let before = &contents[..index];
+	let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+	(
+		before.matches('\n').count() + 1,
+		before[line_start..].chars().count() + 1,
 	)
 }
 
+fn as_tera_expr(value: &str) -> String {
+	format!("{} {} {}", "{{", value, "}}")
+}
+
 fn round_to_two_decimal_places(value: f64) -> f64 {
 	(value * 100.0).round() / 100.0
 }
@@ -173,7 +387,7 @@ mod tests {
 		for flavor in catppuccin::PALETTE.all_flavors() {
 			for color in &flavor.colors {
 				assert_eq!(
-					whiskerify::convert(color.hex.to_string(), None),
+					whiskerify::convert(&color.hex.to_string(), None),
 					format!(
 						"#{}",
 						as_tera_expr(&(color.identifier().to_owned() + ".hex"))
@@ -182,14 +396,81 @@ mod tests {
 				let rgb = css_colors::RGB::from(*color);
 				let hsl = css_colors::HSL::from(*color);
 				assert_eq!(
-					whiskerify::convert(rgb.to_string(), None),
+					whiskerify::convert(&rgb.to_string(), None),
 					as_tera_expr(&(color.identifier().to_owned() + " | css_rgb"))
 				);
 				assert_eq!(
-					whiskerify::convert(hsl.to_string(), None),
+					whiskerify::convert(&hsl.to_string(), None),
 					as_tera_expr(&(color.identifier().to_owned() + " | css_hsl"))
 				);
 			}
 		}
 	}
+
+	#[test]
+	fn hex_variants() {
+		let convert = |text: &str| whiskerify::convert(text, None);
+
+		assert_eq!(convert("#1E1E2E"), "#{{ base.hex | upper }}");
+		assert_eq!(convert("#1e1e2eff"), "#{{ base.hex }}");
+		assert_eq!(
+			convert("#1e1e2e80"),
+			"#{{ base | mod(opacity=0.50) | get(key=\"hex\") }}"
+		);
+		assert_eq!(convert("0x1e1e2e"), "0x{{ base.hex }}");
+		assert_eq!(convert("0X1E1E2E"), "0X{{ base.hex | upper }}");
+		assert_eq!(
+			convert("color = 1E1E2ECC"),
+			"color = {{ base | mod(opacity=0.80) | get(key=\"hex\") | upper }}"
+		);
+		assert_eq!(
+			whiskerify::parse_hex("fA08"),
+			Some(([0xff, 0xaa, 0x00], 0x88))
+		);
+		assert_eq!(convert("#fff"), "#fff");
+		assert_eq!(convert("#000"), "#000");
+		// Short colors need a `#`, and hex digits inside longer words are left alone.
+		assert_eq!(convert("0x1e1e2e80 abc"), "0x1e1e2e80 abc");
+		assert_eq!(convert("sha-1e1e2e1"), "sha-1e1e2e1");
+	}
+
+	#[test]
+	fn only_replace_values() {
+		let contents = "// based on the latte theme\nlatte:\n  background: \"#eff1f5\"\n  commit: 1e1e2e4f-eff1f5\n  name = latte\n";
+		assert_eq!(
+			whiskerify::convert(contents, None),
+			"// based on the latte theme\nlatte:\n  background: \"#{{ base.hex }}\"\n  commit: 1e1e2e4f-eff1f5\n  name = {{ flavor.identifier }}\n"
+		);
+
+		assert_eq!(
+			whiskerify::convert("background: url(//cdn.example.com) #1e1e2e;", None),
+			"background: url(//cdn.example.com) #{{ base.hex }};"
+		);
+
+		let substitutions = whiskerify::substitutions("a {\n  color: rgb(30, 30, 46);\n}\n", None);
+		assert_eq!(substitutions.len(), 1);
+		assert_eq!((substitutions[0].line, substitutions[0].column), (2, 10));
+		assert_eq!(substitutions[0].original, "rgb(30, 30, 46)");
+		assert_eq!(substitutions[0].replacement, "{{ base | css_rgb }}");
+	}
+
+	#[test]
+	fn round_trip_opacity() {
+		for alpha in 0..=u8::MAX {
+			let opacity = whiskerify::opacity_of(alpha);
+			assert_eq!(
+				(opacity.parse::<f64>().unwrap() * 255.0).round(),
+				f64::from(alpha),
+				"opacity {opacity} for alpha {alpha}"
+			);
+			if alpha < u8::MAX {
+				assert_eq!(
+					whiskerify::convert(&format!("#1e1e2e{alpha:02x}"), None),
+					format!("#{{{{ base | mod(opacity={opacity}) | get(key=\"hex\") }}}}")
+				);
+			}
+		}
+		assert_eq!(whiskerify::opacity_of(0x80), "0.50");
+		assert_eq!(whiskerify::opacity_of(0x02), "0.008");
+	}
 }
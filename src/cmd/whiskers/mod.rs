@@ -0,0 +1,359 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{bail, Context, Result};
+use log::warn;
+use serde::Serialize;
+
+use crate::cache::Cache;
+use crate::cli::{Format, WhiskersCommand};
+use crate::cmd::query::property::{self, UNSET};
+use crate::format::display;
+use crate::github::{self, fetch_property};
+use crate::sources::Sources;
+use crate::token;
+
+/// Directories that never contain a port's own files.
+const IGNORED_DIRECTORIES: &[&str] = &[".git", "node_modules", "target"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verdict {
+	/// Templates that are built with Whiskers.
+	Adopted,
+	/// Templates without any sign of Whiskers building them, or Whiskers being run without any templates.
+	Partial,
+	NotAdopted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Detection {
+	pub port: String,
+	pub verdict: Verdict,
+	pub evidence: Vec<String>,
+	/// The repository's `whiskers` custom property, to compare against (only for repositories).
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub custom_property: Option<String>,
+}
+
+/// Detect or report on the usage of Whiskers.
+///
+/// # Errors
+///
+/// Returns an error if the files or repository to check can't be read or fetched.
+pub fn handle(
+	cache: &mut Cache,
+	sources: &Sources,
+	command: WhiskersCommand,
+	format: Format,
+) -> Result<()> {
+	match command {
+		WhiskersCommand::Detect {
+			path,
+			repo,
+			all,
+			token,
+		} => {
+			let detections = if all {
+				let token = token::require(token, &sources.github, cache)?;
+				property::values(cache, &sources.github, "whiskers", &token)?
+					.into_iter()
+					.map(|(repository, value)| {
+						detect_remote(cache, &sources.github, &repository, value, &token)
+					})
+					.collect::<Result<Vec<_>>>()?
+			} else if repo.is_empty() {
+				let path = path.unwrap_or_else(|| PathBuf::from("."));
+				vec![detect_local(&path)?]
+			} else {
+				let token = token::require(token, &sources.github, cache)?;
+				repo.iter()
+					.map(|repository| {
+						let value = fetch_property(
+							cache,
+							&sources.github,
+							repository,
+							"whiskers",
+							token.clone(),
+						)?;
+						detect_remote(cache, &sources.github, repository, value, &token)
+					})
+					.collect::<Result<Vec<_>>>()?
+			};
+			display(detections, false, format)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Inspect a local checkout of a port.
+fn detect_local(root: &Path) -> Result<Detection> {
+	if !root.is_dir() {
+		bail!("'{}' is not a directory", root.display());
+	}
+
+	let mut paths = vec![];
+	walk(root, root, &mut paths)?;
+	paths.sort();
+	let (verdict, evidence) = detect(&paths, |path| {
+		fs::read_to_string(root.join(path))
+			.with_context(|| format!("Failed to read '{}'", root.join(path).display()))
+	})?;
+
+	Ok(Detection {
+		port: root.canonicalize()?.file_name().map_or_else(
+			|| root.display().to_string(),
+			|name| name.to_string_lossy().to_string(),
+		),
+		verdict,
+		evidence,
+		custom_property: None,
+	})
+}
+
+/// Collect the paths of all files below a directory, relative to the root and with `/` separators like in git trees.
+fn walk(root: &Path, directory: &Path, paths: &mut Vec<String>) -> Result<()> {
+	for entry in fs::read_dir(directory)? {
+		let entry = entry?;
+		let path = entry.path();
+		if entry.file_type()?.is_dir() {
+			if !IGNORED_DIRECTORIES.contains(&entry.file_name().to_string_lossy().as_ref()) {
+				walk(root, &path, paths)?;
+			}
+		} else if let Ok(relative) = path.strip_prefix(root) {
+			paths.push(
+				relative
+					.components()
+					.map(|component| component.as_os_str().to_string_lossy())
+					.collect::<Vec<_>>()
+					.join("/"),
+			);
+		}
+	}
+	Ok(())
+}
+
+/// Inspect a repository of the organization on its default branch using the tree API, comparing the verdict with the value of its `whiskers` custom property.
+fn detect_remote(
+	cache: &mut Cache,
+	github: &github::Github,
+	repository: &str,
+	custom_property: Option<String>,
+	token: &str,
+) -> Result<Detection> {
+	let tree = github::fetch_files(cache, github, repository, token)?;
+	let paths = tree
+		.tree
+		.into_iter()
+		.map(|entry| entry.path)
+		.collect::<Vec<_>>();
+	let (verdict, mut evidence) = detect(&paths, |path| {
+		github::fetch_file(cache, github, repository, path, token)
+	})?;
+	if tree.truncated {
+		warn!("the tree of {repository} is too large for GitHub to list in full, so its verdict may be wrong");
+		evidence.push("only some files were listed, as the tree is too large".to_string());
+	}
+
+	Ok(Detection {
+		port: repository.to_string(),
+		verdict,
+		evidence,
+		custom_property: Some(custom_property.unwrap_or_else(|| UNSET.to_string())),
+	})
+}
+
+/// Decide whether a port uses Whiskers from the paths of its files, reading the contents of the few files that matter. Whiskers is adopted if there are templates and either one of them has Whiskers frontmatter or a justfile recipe runs Whiskers.
+fn detect(
+	paths: &[String],
+	mut read: impl FnMut(&str) -> Result<String>,
+) -> Result<(Verdict, Vec<String>)> {
+	let file_name = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+	let mut evidence = vec![];
+
+	let templates = paths
+		.iter()
+		.filter(|path| {
+			Path::new(path)
+				.extension()
+				.is_some_and(|extension| extension == "tera")
+		})
+		.collect::<Vec<_>>();
+	for template in &templates {
+		evidence.push(format!("template {template}"));
+	}
+
+	let mut frontmatter = false;
+	for template in &templates {
+		if has_whiskers_frontmatter(&read(template)?) {
+			evidence.push(format!("Whiskers frontmatter in {template}"));
+			frontmatter = true;
+			break;
+		}
+	}
+
+	let mut justfile = false;
+	for path in paths.iter().filter(|path| {
+		matches!(
+			file_name(path).as_str(),
+			"justfile" | "Justfile" | ".justfile"
+		)
+	}) {
+		if runs_whiskers(&read(path)?) {
+			evidence.push(format!("recipe running Whiskers in {path}"));
+			justfile = true;
+			break;
+		}
+	}
+
+	let generated = paths
+		.iter()
+		.filter(|path| !templates.contains(path))
+		.filter(|path| {
+			let name = file_name(path).to_lowercase();
+			catppuccin::PALETTE
+				.all_flavors()
+				.iter()
+				.any(|flavor| name.contains(flavor.identifier()))
+		})
+		.collect::<Vec<_>>();
+	let all_flavors = catppuccin::PALETTE.all_flavors().iter().all(|flavor| {
+		generated
+			.iter()
+			.any(|path| file_name(path).to_lowercase().contains(flavor.identifier()))
+	});
+	if all_flavors {
+		evidence.push(format!(
+			"theme files for every flavor, e.g. {}",
+			generated[0]
+		));
+	}
+
+	let verdict = match (!templates.is_empty(), frontmatter || justfile) {
+		(true, true) => Verdict::Adopted,
+		(true, false) | (false, true) => Verdict::Partial,
+		(false, false) => Verdict::NotAdopted,
+	};
+	Ok((verdict, evidence))
+}
+
+/// Whether a template starts with YAML frontmatter that configures Whiskers.
+fn has_whiskers_frontmatter(contents: &str) -> bool {
+	let Some(rest) = contents.trim_start_matches('\u{feff}').strip_prefix("---") else {
+		return false;
+	};
+	rest.lines()
+		.skip(1)
+		.take_while(|line| line.trim_end() != "---")
+		.any(|line| line.starts_with("whiskers:"))
+}
+
+/// Whether any recipe of a justfile runs the `whiskers` command.
+fn runs_whiskers(contents: &str) -> bool {
+	contents
+		.lines()
+		.filter(|line| line.starts_with([' ', '\t']))
+		.any(|line| {
+			line.split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+				.any(|word| word == "whiskers")
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use color_eyre::eyre::eyre;
+
+	use super::{detect, detect_remote, Verdict};
+	use crate::cache::Cache;
+	use crate::github::{Github, Tree, TreeEntry};
+
+	fn detect_files(files: &[(&str, &str)]) -> (Verdict, Vec<String>) {
+		let contents = files.iter().copied().collect::<HashMap<_, _>>();
+		let paths = files
+			.iter()
+			.map(|(path, _)| (*path).to_string())
+			.collect::<Vec<_>>();
+		detect(&paths, |path| {
+			contents
+				.get(path)
+				.map(ToString::to_string)
+				.ok_or_else(|| eyre!("no file {path}"))
+		})
+		.unwrap()
+	}
+
+	#[test]
+	fn detect_whiskers_usage() {
+		let (verdict, evidence) = detect_files(&[
+			(
+				"templates/theme.tera",
+				"---\nwhiskers:\n  version: 2.0.0\n---\n{{ base.hex }}",
+			),
+			("justfile", "build:\n\twhiskers templates/theme.tera\n"),
+			("themes/latte.json", ""),
+			("themes/frappe.json", ""),
+			("themes/macchiato.json", ""),
+			("themes/mocha.json", ""),
+		]);
+		assert_eq!(verdict, Verdict::Adopted);
+		assert_eq!(
+			evidence,
+			vec![
+				"template templates/theme.tera",
+				"Whiskers frontmatter in templates/theme.tera",
+				"recipe running Whiskers in justfile",
+				"theme files for every flavor, e.g. themes/latte.json",
+			]
+		);
+
+		let (verdict, _) = detect_files(&[("port.tera", "{{ base.hex }}")]);
+		assert_eq!(verdict, Verdict::Partial);
+
+		let (verdict, evidence) = detect_files(&[
+			("justfile", "# whiskers someday\nbuild:\n\tnpm run build\n"),
+			("mocha.css", ""),
+		]);
+		assert_eq!(verdict, Verdict::NotAdopted);
+		assert!(evidence.is_empty());
+	}
+
+	#[test]
+	fn detect_from_cache_offline() {
+		let path = std::env::temp_dir().join("purr-tests/detect/store.json");
+		let _ = std::fs::remove_file(&path);
+		let mut cache = Cache::new(path, false, 60).with_offline(true);
+		let entry = |path: &str| TreeEntry {
+			path: path.to_string(),
+			r#type: "blob".to_string(),
+		};
+		cache
+			.save(
+				"files-nvim",
+				Tree {
+					tree: vec![entry("nvim.tera"), entry("justfile")],
+					truncated: true,
+				},
+			)
+			.unwrap();
+		cache
+			.save("file-nvim/nvim.tera", "{{ base.hex }}".to_string())
+			.unwrap();
+		cache
+			.save(
+				"file-nvim/justfile",
+				"build:\n\twhiskers nvim.tera\n".to_string(),
+			)
+			.unwrap();
+
+		let detection =
+			detect_remote(&mut cache, &Github::default(), "nvim", None, "token").unwrap();
+		assert_eq!(detection.verdict, Verdict::Adopted);
+		assert_eq!(detection.custom_property.as_deref(), Some("unset"));
+		assert!(detection.evidence.last().unwrap().contains("too large"));
+		assert!(detect_remote(&mut cache, &Github::default(), "bat", None, "token").is_err());
+	}
+}
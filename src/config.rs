@@ -0,0 +1,61 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Context, Result};
+use etcetera::{choose_base_strategy, BaseStrategy};
+use serde::Deserialize;
+
+use crate::sources::Source;
+
+/// User configuration, read from `purr/config.yml` in the platform configuration directory.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+	pub sources: SourcesConfig,
+	pub github: GithubConfig,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SourcesConfig {
+	pub ports: Option<Source>,
+	pub userstyles: Option<Source>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GithubConfig {
+	pub api: Option<String>,
+	pub graphql: Option<String>,
+	pub organization: Option<String>,
+}
+
+impl Config {
+	/// # Panics
+	///
+	/// Panics if the home directory can't be determined.
+	#[must_use]
+	pub fn default_path() -> PathBuf {
+		choose_base_strategy()
+			.unwrap()
+			.config_dir()
+			.join("purr/config.yml")
+	}
+
+	/// Load the configuration file at the given path, falling back to the default configuration if it does not exist.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the file can't be read or parsed.
+	pub fn load(path: &Path) -> Result<Self> {
+		match fs::read_to_string(path) {
+			Ok(contents) => serde_yaml::from_str(&contents)
+				.with_context(|| format!("Failed to parse config file '{}'", path.display())),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+			Err(err) => {
+				Err(err).with_context(|| format!("Failed to read config file '{}'", path.display()))
+			}
+		}
+	}
+}
@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use color_eyre::eyre::{Context, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::cli::Format;
+
+/// Print a list of results in the given format, or just the number of results if `count` is set.
+///
+/// # Errors
+///
+/// Returns an error if the results can't be serialized in the given format.
+pub fn display<T: Serialize>(result: Vec<T>, count: bool, format: Format) -> Result<()> {
+	if count {
+		println!("{}", result.len());
+		return Ok(());
+	}
+
+	let rows = result
+		.into_iter()
+		.map(serde_json::to_value)
+		.collect::<Result<Vec<_>, _>>()
+		.context("Failed to serialize results")?;
+
+	print!("{}", render(&rows, format)?);
+	Ok(())
+}
+
+/// Print results grouped under named buckets. JSON and YAML output nest the results under each group, while the line-based formats add the group as the first column of each result.
+///
+/// # Errors
+///
+/// Returns an error if the results can't be serialized in the given format.
+pub fn display_grouped<T: Serialize>(
+	groups: BTreeMap<String, Vec<T>>,
+	key: &str,
+	count: bool,
+	format: Format,
+) -> Result<()> {
+	let nested = matches!(format, Format::Json | Format::Yaml);
+
+	if count {
+		if nested {
+			return display_value(
+				groups
+					.into_iter()
+					.map(|(group, results)| (group, results.len()))
+					.collect::<BTreeMap<_, _>>(),
+				format,
+			);
+		}
+		return display(
+			groups
+				.into_iter()
+				.map(|(group, results)| serde_json::json!({ key: group, "count": results.len() }))
+				.collect(),
+			false,
+			format,
+		);
+	}
+
+	if nested {
+		return display_value(groups, format);
+	}
+
+	let mut rows = vec![];
+	for (group, results) in groups {
+		for result in results {
+			let mut row = Map::new();
+			row.insert(key.to_string(), Value::String(group.clone()));
+			match serde_json::to_value(result).context("Failed to serialize results")? {
+				Value::Object(fields) => row.extend(fields.into_iter().filter(|(k, _)| k != key)),
+				value => {
+					row.insert("value".to_string(), value);
+				}
+			}
+			rows.push(Value::Object(row));
+		}
+	}
+	display(rows, false, format)
+}
+
+/// Print a single result (e.g. one port or a summary) in the given format.
+///
+/// # Errors
+///
+/// Returns an error if the result can't be serialized in the given format.
+pub fn display_value<T: Serialize>(value: T, format: Format) -> Result<()> {
+	let value = serde_json::to_value(value).context("Failed to serialize results")?;
+
+	print!(
+		"{}",
+		match format {
+			Format::Json => format!("{}\n", serde_json::to_string_pretty(&value)?),
+			Format::Ndjson => format!("{}\n", serde_json::to_string(&value)?),
+			Format::Yaml => serde_yaml::to_string(&value)?,
+			Format::Table | Format::Csv | Format::Tsv => render(&[value], format)?,
+		}
+	);
+	Ok(())
+}
+
+/// Render rows of results as a string in the given format.
+///
+/// # Errors
+///
+/// Returns an error if the rows can't be serialized in the given format.
+pub fn render(rows: &[Value], format: Format) -> Result<String> {
+	Ok(match format {
+		Format::Json => format!("{}\n", serde_json::to_string_pretty(rows)?),
+		Format::Ndjson => rows.iter().fold(String::new(), |mut out, row| {
+			let _ = writeln!(out, "{row}");
+			out
+		}),
+		Format::Yaml => render_yaml(rows)?,
+		Format::Table => render_table(rows),
+		Format::Csv => render_delimited(rows, ',', escape_csv),
+		Format::Tsv => render_delimited(rows, '\t', escape_tsv),
+	})
+}
+
+/// Rows keyed by identifier are written as a mapping, mirroring the layout of ports.yml.
+fn render_yaml(rows: &[Value]) -> Result<String> {
+	let keyed = rows
+		.iter()
+		.map(|row| {
+			let mut row = row.as_object()?.clone();
+			match row.shift_remove("identifier")? {
+				Value::String(identifier) if !row.is_empty() => {
+					Some((identifier, Value::Object(row)))
+				}
+				_ => None,
+			}
+		})
+		.collect::<Option<Map<_, _>>>();
+
+	Ok(match keyed {
+		Some(keyed) if !rows.is_empty() => serde_yaml::to_string(&keyed)?,
+		_ => serde_yaml::to_string(rows)?,
+	})
+}
+
+/// Column names in order of first appearance, or `None` if the rows are not all objects.
+fn columns(rows: &[Value]) -> Option<Vec<String>> {
+	let mut columns: Vec<String> = vec![];
+	for row in rows {
+		for key in row.as_object()?.keys() {
+			if !columns.contains(key) {
+				columns.push(key.clone());
+			}
+		}
+	}
+	Some(columns)
+}
+
+fn cell(value: &Value, separator: &str) -> String {
+	match value {
+		Value::Null => String::new(),
+		Value::String(s) => s.clone(),
+		Value::Array(arr) => arr
+			.iter()
+			.map(|v| cell(v, separator))
+			.collect::<Vec<_>>()
+			.join(separator),
+		_ => value.to_string(),
+	}
+}
+
+fn cells(rows: &[Value], separator: &str) -> (Option<Vec<String>>, Vec<Vec<String>>) {
+	let columns = columns(rows);
+	let cells = rows
+		.iter()
+		.map(|row| match &columns {
+			Some(columns) => columns
+				.iter()
+				.map(|column| {
+					row.get(column)
+						.map_or(String::new(), |v| cell(v, separator))
+				})
+				.collect(),
+			None => vec![cell(row, separator)],
+		})
+		.collect();
+	(columns, cells)
+}
+
+fn render_table(rows: &[Value]) -> String {
+	let (columns, cells) = cells(rows, ", ");
+	let lines = columns.into_iter().chain(cells).collect::<Vec<_>>();
+
+	let mut widths: Vec<usize> = vec![];
+	for line in &lines {
+		for (i, cell) in line.iter().enumerate() {
+			let width = cell.chars().count();
+			match widths.get_mut(i) {
+				Some(w) => *w = (*w).max(width),
+				None => widths.push(width),
+			}
+		}
+	}
+
+	lines.iter().fold(String::new(), |mut out, line| {
+		let row = line
+			.iter()
+			.zip(&widths)
+			.map(|(cell, width)| format!("{cell:<width$}"))
+			.collect::<Vec<_>>()
+			.join("  ");
+		let _ = writeln!(out, "{}", row.trim_end());
+		out
+	})
+}
+
+fn render_delimited(rows: &[Value], delimiter: char, escape: fn(&str) -> String) -> String {
+	let (columns, cells) = cells(rows, ",");
+
+	columns
+		.into_iter()
+		.chain(cells)
+		.fold(String::new(), |mut out, line| {
+			let line = line
+				.iter()
+				.map(|cell| escape(cell))
+				.collect::<Vec<_>>()
+				.join(&delimiter.to_string());
+			let _ = writeln!(out, "{line}");
+			out
+		})
+}
+
+fn escape_csv(cell: &str) -> String {
+	if cell.contains([',', '"', '\n', '\r']) {
+		format!("\"{}\"", cell.replace('"', "\"\""))
+	} else {
+		cell.to_string()
+	}
+}
+
+fn escape_tsv(cell: &str) -> String {
+	cell.replace('\\', "\\\\")
+		.replace('\t', "\\t")
+		.replace('\n', "\\n")
+		.replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::render;
+	use crate::cli::Format;
+
+	#[test]
+	fn render_formats() {
+		let rows = vec![
+			json!({ "identifier": "nvim", "name": "Neovim", "platform": ["linux", "macos"] }),
+			json!({ "identifier": "bat", "name": "bat \"cat\"", "platform": [] }),
+		];
+
+		assert_eq!(
+			render(&rows, Format::Table).unwrap(),
+			"identifier  name       platform\nnvim        Neovim     linux, macos\nbat         bat \"cat\"\n"
+		);
+		assert_eq!(
+			render(&rows, Format::Csv).unwrap(),
+			"identifier,name,platform\nnvim,Neovim,\"linux,macos\"\nbat,\"bat \"\"cat\"\"\",\n"
+		);
+		assert_eq!(
+			render(&rows, Format::Tsv).unwrap(),
+			"identifier\tname\tplatform\nnvim\tNeovim\tlinux,macos\nbat\tbat \"cat\"\t\n"
+		);
+		assert_eq!(
+			render(&rows, Format::Ndjson).unwrap(),
+			"{\"identifier\":\"nvim\",\"name\":\"Neovim\",\"platform\":[\"linux\",\"macos\"]}\n{\"identifier\":\"bat\",\"name\":\"bat \\\"cat\\\"\",\"platform\":[]}\n"
+		);
+		assert_eq!(
+			render(&rows, Format::Yaml).unwrap(),
+			"nvim:\n  name: Neovim\n  platform:\n  - linux\n  - macos\nbat:\n  name: bat \"cat\"\n  platform: []\n"
+		);
+		assert_eq!(
+			render(&[json!("nvim"), json!("bat")], Format::Csv).unwrap(),
+			"nvim\nbat\n"
+		);
+	}
+}
@@ -1,46 +1,300 @@
-use color_eyre::Result;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use reqwest::blocking::Client;
+use color_eyre::{eyre::bail, Result};
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use url::Url;
 
-use graphql_client::{reqwest::post_graphql_blocking as post_graphql, GraphQLQuery};
+use graphql_client::GraphQLQuery;
+use log::warn;
 use repositories::{
 	RepositoriesOrganizationRepositories, RepositoriesOrganizationRepositoriesNodes,
 };
 
 use crate::cache::Cache;
+use crate::token;
+
+/// ISO 8601 timestamp, e.g. `2024-01-01T00:00:00Z`.
+type DateTime = String;
+#[allow(clippy::upper_case_acronyms)]
+type URI = String;
 
 #[derive(GraphQLQuery)]
 #[graphql(
 	schema_path = "src/schema.graphql",
 	query_path = "src/repositories.graphql",
-	response_derives = "Debug,Serialize,Clone"
+	response_derives = "Debug,Default,Serialize,Clone,PartialEq"
 )]
-struct Repositories;
+pub struct Repositories;
 
-#[must_use]
+/// A repository in the organization, as returned by [`fetch_all_repositories`].
+pub type Repository = RepositoriesOrganizationRepositoriesNodes;
+
+pub const DEFAULT_API: &str = "https://api.github.com";
+pub const DEFAULT_ORGANIZATION: &str = "catppuccin";
+
+/// The GitHub API to use and the organization to query, e.g. a mirror organization, GitHub Enterprise or a local stand-in server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Github {
+	/// Base URL of the REST API.
+	pub api: String,
+	/// URL of the GraphQL endpoint.
+	pub graphql: String,
+	pub organization: String,
+}
+
+impl Default for Github {
+	fn default() -> Self {
+		Github {
+			api: DEFAULT_API.to_string(),
+			graphql: Github::graphql_endpoint(DEFAULT_API),
+			organization: DEFAULT_ORGANIZATION.to_string(),
+		}
+	}
+}
+
+impl Github {
+	/// The GraphQL endpoint that belongs to a REST API base URL, which is `/api/graphql` rather than `/api/v3/graphql` for GitHub Enterprise.
+	#[must_use]
+	pub fn graphql_endpoint(api: &str) -> String {
+		let api = api.trim_end_matches('/');
+		match api.strip_suffix("/api/v3") {
+			Some(host) => format!("{host}/api/graphql"),
+			None => format!("{api}/graphql"),
+		}
+	}
+
+	/// The host the API belongs to, e.g. `github.com` or `ghe.example.com`, including the port if there is one. Tokens are discovered for this host.
+	#[must_use]
+	pub fn host(&self) -> String {
+		let Ok(url) = url::Url::parse(&self.api) else {
+			return self.api.clone();
+		};
+		match (url.host_str(), url.port()) {
+			(Some("api.github.com"), None) => token::GITHUB_HOST.to_string(),
+			(Some(host), None) => host.to_string(),
+			(Some(host), Some(port)) => format!("{host}:{port}"),
+			(None, _) => self.api.clone(),
+		}
+	}
+
+	fn url(&self, path: &str) -> String {
+		format!("{}/{path}", self.api.trim_end_matches('/'))
+	}
+
+	/// Cache keys of anything but the default API and organization include them, so that their data never mixes.
+	fn key(&self, key: &str) -> String {
+		if self.api == DEFAULT_API && self.organization == DEFAULT_ORGANIZATION {
+			key.to_string()
+		} else {
+			format!(
+				"{key}@{}/{}",
+				self.api.trim_end_matches('/'),
+				self.organization
+			)
+		}
+	}
+}
+
+/// Failures of GitHub API requests that users can do something about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GithubError {
+	/// The token is missing, invalid or lacks the required permissions.
+	Unauthorized { message: String },
+	/// The rate limit was exceeded, resetting at the given time (if known).
+	RateLimited { reset: Option<SystemTime> },
+	/// The GraphQL API responded with errors.
+	GraphQl { errors: Vec<String> },
+	/// A repository does not have an expected custom property.
+	MissingProperty {
+		repository: String,
+		property: String,
+	},
+	/// The requested resource does not exist (or is not visible with the token).
+	NotFound { resource: String },
+}
+
+impl GithubError {
+	/// Exit code for the process when failing with this error.
+	#[must_use]
+	pub fn exit_code(&self) -> u8 {
+		match self {
+			GithubError::Unauthorized { .. } => 3,
+			GithubError::RateLimited { .. } => 4,
+			GithubError::GraphQl { .. } => 5,
+			GithubError::MissingProperty { .. } => 6,
+			GithubError::NotFound { .. } => 7,
+		}
+	}
+
+	/// A hint on how to resolve the error, shown below the error report.
+	#[must_use]
+	pub fn suggestion(&self) -> String {
+		match self {
+			GithubError::Unauthorized { .. } => "Check that the GitHub token (from --token, GITHUB_TOKEN, GH_TOKEN, the GitHub CLI or git credentials) is valid and has not expired".to_string(),
+			GithubError::RateLimited { .. } => "Pass a GitHub token with --token for a higher rate limit, or use cached data with --offline".to_string(),
+			GithubError::GraphQl { .. } => "Check that the token has access to the organization's repositories".to_string(),
+			GithubError::MissingProperty { .. } => "Ask an organization admin to set the property on the repository".to_string(),
+			GithubError::NotFound { .. } => "Check the spelling of the repository name".to_string(),
+		}
+	}
+
+	/// Classify an unsuccessful response, if it is one of the known failures.
+	fn from_response(
+		status: StatusCode,
+		headers: &HeaderMap,
+		body: &str,
+		resource: &str,
+	) -> Option<Self> {
+		let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+		// Secondary rate limits are answered with a 403 as well, but with a Retry-After header or at least a message saying so.
+		let secondary =
+			header("retry-after").is_some() || body.to_lowercase().contains("secondary rate limit");
+
+		match status {
+			StatusCode::UNAUTHORIZED => Some(GithubError::Unauthorized {
+				message: "bad credentials".to_string(),
+			}),
+			StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+				if status == StatusCode::TOO_MANY_REQUESTS
+					|| header("x-ratelimit-remaining") == Some("0")
+					|| secondary =>
+			{
+				let retry_after = header("retry-after")
+					.and_then(|seconds| seconds.parse().ok())
+					.map(|seconds| SystemTime::now() + Duration::from_secs(seconds));
+				Some(GithubError::RateLimited {
+					reset: retry_after.or_else(|| {
+						header("x-ratelimit-reset")
+							.and_then(|reset| reset.parse().ok())
+							.map(|reset| UNIX_EPOCH + Duration::from_secs(reset))
+					}),
+				})
+			}
+			StatusCode::FORBIDDEN => Some(GithubError::Unauthorized {
+				message: format!("access to {resource} is forbidden"),
+			}),
+			StatusCode::NOT_FOUND => Some(GithubError::NotFound {
+				resource: resource.to_string(),
+			}),
+			_ => None,
+		}
+	}
+}
+
+impl fmt::Display for GithubError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			GithubError::Unauthorized { message } => {
+				write!(f, "GitHub rejected the request: {message}")
+			}
+			GithubError::RateLimited { reset: None } => {
+				write!(f, "GitHub API rate limit exceeded")
+			}
+			GithubError::RateLimited { reset: Some(reset) } => {
+				let minutes = reset
+					.duration_since(SystemTime::now())
+					.unwrap_or_default()
+					.as_secs()
+					.div_ceil(60);
+				write!(
+					f,
+					"GitHub API rate limit exceeded, it resets in {minutes} minute{}",
+					if minutes == 1 { "" } else { "s" }
+				)
+			}
+			GithubError::GraphQl { errors } => {
+				write!(f, "GitHub GraphQL API error: {}", errors.join("; "))
+			}
+			GithubError::MissingProperty {
+				repository,
+				property,
+			} => write!(
+				f,
+				"repository '{repository}' has no '{property}' custom property"
+			),
+			GithubError::NotFound { resource } => write!(f, "{resource} was not found on GitHub"),
+		}
+	}
+}
+
+impl std::error::Error for GithubError {}
+
+/// Turn unsuccessful responses into errors, using [`GithubError`] for the known failures.
+fn check_response(response: Response, resource: &str) -> Result<Response> {
+	let Err(err) = response.error_for_status_ref() else {
+		return Ok(response);
+	};
+	let (status, headers) = (response.status(), response.headers().clone());
+	let body = response.text().unwrap_or_default();
+	match GithubError::from_response(status, &headers, &body, resource) {
+		Some(err) => Err(err.into()),
+		None => Err(err.into()),
+	}
+}
+
+/// A page of the organization's repositories, starting after the given cursor.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
 pub fn fetch_repositories(
 	client: &Client,
+	github: &Github,
 	cursor: Option<std::string::String>,
-) -> RepositoriesOrganizationRepositories {
-	let variables = repositories::Variables { cursor };
+) -> Result<RepositoriesOrganizationRepositories> {
+	let variables = repositories::Variables {
+		organization: github.organization.clone(),
+		cursor,
+	};
 
-	let reponse =
-		post_graphql::<Repositories, _>(client, "https://api.github.com/graphql", variables)
-			.unwrap();
+	let response = check_response(
+		client
+			.post(&github.graphql)
+			.json(&Repositories::build_query(variables))
+			.send()?,
+		"the GraphQL API",
+	)?
+	.json::<graphql_client::Response<repositories::ResponseData>>()?;
 
-	let data: repositories::ResponseData = reponse.data.expect("missing response data");
+	if let Some(errors) = response.errors.filter(|errors| !errors.is_empty()) {
+		return Err(GithubError::GraphQl {
+			errors: errors.into_iter().map(|err| err.message).collect(),
+		}
+		.into());
+	}
 
-	data.organization
-		.expect("missing organization")
-		.repositories
+	Ok(response
+		.data
+		.ok_or_else(|| GithubError::GraphQl {
+			errors: vec!["missing response data".to_string()],
+		})?
+		.organization
+		.ok_or_else(|| GithubError::NotFound {
+			resource: format!("organization '{}'", github.organization),
+		})?
+		.repositories)
 }
 
+/// All repositories of the organization, fetching every page.
+///
+/// # Errors
+///
+/// Returns an error if a request fails.
 pub fn fetch_all_repositories(
 	cache: &mut Cache,
+	github: &Github,
 	token: &str,
 ) -> Result<Vec<Option<RepositoriesOrganizationRepositoriesNodes>>> {
-	cache.get_or("all-repositories", || {
+	cache.get_or(&github.key("all-repositories"), || {
 		let client = Client::builder()
 			.user_agent("catppuccin-purr")
 			.default_headers(
@@ -56,9 +310,9 @@ pub fn fetch_all_repositories(
 		let mut repositories: Vec<Option<RepositoriesOrganizationRepositoriesNodes>> = vec![];
 
 		loop {
-			let data = fetch_repositories(&client, cursor);
+			let data = fetch_repositories(&client, github, cursor)?;
 
-			repositories.extend(data.nodes.expect("repositories nodes is null"));
+			repositories.extend(data.nodes.unwrap_or_default());
 
 			if !data.page_info.has_next_page {
 				break;
@@ -70,19 +324,97 @@ pub fn fetch_all_repositories(
 	})
 }
 
-pub fn rest(path: &str, token: Option<String>) -> Result<reqwest::blocking::Response> {
-	let client = Client::new();
-	let request = client
-		.get(format!("https://api.github.com/{path}"))
+/// When the list of all repositories was fetched, if it is cached.
+#[must_use]
+pub fn all_repositories_fetched_at(cache: &Cache, github: &Github) -> Option<SystemTime> {
+	cache
+		.entry(&github.key("all-repositories"))
+		.map(crate::cache::Entry::timestamp)
+}
+
+/// Send an authenticated (if a token is given) request to the REST API.
+///
+/// # Errors
+///
+/// Returns an error if the request fails or GitHub responds with an error.
+pub fn rest(github: &Github, path: &str, token: Option<String>) -> Result<Response> {
+	check_response(send(github, path, token)?, resource(path))
+}
+
+/// Like [`rest`], but waiting and retrying when rate limited, as long as the limit resets soon enough.
+fn rest_with_retries(github: &Github, path: &str, token: Option<&str>) -> Result<Response> {
+	const MAX_ATTEMPTS: usize = 3;
+	const MAX_WAIT_SECONDS: u64 = 60;
+
+	for attempt in 1.. {
+		let response = send(github, path, token.map(ToString::to_string))?;
+		let wait = rate_limit_wait(&response);
+		match wait {
+			Some(wait) if attempt < MAX_ATTEMPTS && wait.as_secs() <= MAX_WAIT_SECONDS => {
+				warn!("rate limited by GitHub, retrying in {}s", wait.as_secs());
+				std::thread::sleep(wait);
+			}
+			_ => return check_response(response, resource(path)),
+		}
+	}
+	unreachable!()
+}
+
+/// Joins segments into an API path, percent-encoding each of them, so that branch names and file paths can't escape theirs.
+fn encode_path<'a>(segments: impl IntoIterator<Item = &'a str>) -> String {
+	let mut url = Url::parse("http://localhost").expect("the base URL is valid");
+	url.path_segments_mut()
+		.expect("the base URL has a path")
+		.extend(segments);
+	url.path().trim_start_matches('/').to_string()
+}
+
+fn send(github: &Github, path: &str, token: Option<String>) -> Result<Response> {
+	Ok(request(github, path, token).send()?)
+}
+
+/// Requests without a token still use one discovered for the API's host if possible, since anonymous requests have a much lower rate limit.
+fn request(github: &Github, path: &str, token: Option<String>) -> RequestBuilder {
+	let token = token.or_else(|| token::discover(&github.host()));
+	let request = Client::new()
+		.get(github.url(path))
 		.header(reqwest::header::USER_AGENT, "catppuccin-purr");
-	Ok(if let Some(token) = token {
-		request
-			.header(reqwest::header::AUTHORIZATION, format!("Bearer {token}"))
-			.send()?
+	if let Some(token) = token {
+		request.header(reqwest::header::AUTHORIZATION, format!("Bearer {token}"))
 	} else {
-		request.send()?
+		request
+	}
+}
+
+/// The path of a REST request without its query string, for error messages.
+fn resource(path: &str) -> &str {
+	path.split('?').next().unwrap_or(path)
+}
+
+/// How long to wait before retrying a rate limited response, based on the `Retry-After` header or, when no requests remain, the `X-RateLimit-Reset` time.
+fn rate_limit_wait(response: &Response) -> Option<Duration> {
+	if !matches!(
+		response.status(),
+		StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+	) {
+		return None;
+	}
+	let header = |name| {
+		response
+			.headers()
+			.get(name)
+			.and_then(|value| value.to_str().ok())
+			.and_then(|value| value.parse::<u64>().ok())
+	};
+
+	if let Some(seconds) = header("retry-after") {
+		return Some(Duration::from_secs(seconds));
+	}
+	if header("x-ratelimit-remaining") == Some(0) {
+		let reset = UNIX_EPOCH + Duration::from_secs(header("x-ratelimit-reset")?);
+		return Some(reset.duration_since(SystemTime::now()).unwrap_or_default());
 	}
-	.error_for_status()?)
+	None
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -93,25 +425,736 @@ pub struct RepositoryResponse {
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CustomProperty {
 	pub property_name: String,
-	pub value: String,
+	pub value: Option<String>,
+}
+
+/// Custom property values of a repository, as listed by the organization endpoint.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryProperties {
+	pub repository_name: String,
+	pub properties: Vec<CustomProperty>,
 }
 
-pub fn fetch_whiskers_status(cache: &mut Cache, repository: &str, token: String) -> Result<String> {
-	let cache_key = format!("whiskers-{repository}");
-	if let Some(cached) = cache.get::<String>(&cache_key) {
-		return Ok(cached.to_string());
+/// Cache key of a custom property value. Whiskers statuses keep the `whiskers-` keys they were always cached under, other properties get their own namespace so that they never collide with other cache entries.
+fn property_key(property: &str, repository: &str) -> String {
+	if property == "whiskers" {
+		format!("whiskers-{repository}")
+	} else {
+		format!("property-{property}-{repository}")
 	}
+}
 
-	let props = rest(
-		&format!("repos/catppuccin/{repository}/properties/values"),
-		Some(token),
-	)?
-	.json::<Vec<CustomProperty>>()?;
+fn missing_property(repository: &str, property: &str) -> GithubError {
+	GithubError::MissingProperty {
+		repository: repository.to_string(),
+		property: property.to_string(),
+	}
+}
 
-	let property = props
+/// Value of a custom property of a repository, or `None` if it isn't set.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+pub fn fetch_property(
+	cache: &mut Cache,
+	github: &Github,
+	repository: &str,
+	property: &str,
+	token: String,
+) -> Result<Option<String>> {
+	cache.get_or(&github.key(&property_key(property, repository)), || {
+		let props = rest(
+			github,
+			&format!(
+				"repos/{}/{repository}/properties/values",
+				github.organization
+			),
+			Some(token),
+		)?
+		.json::<Vec<CustomProperty>>()?;
+
+		Ok(props
+			.into_iter()
+			.find(|prop| prop.property_name == property)
+			.and_then(|prop| prop.value))
+	})
+}
+
+/// Values of a custom property of many repositories, keyed by repository name. Values that aren't cached are fetched for the whole organization at once (page by page) and then cached per repository; any repository still missing is fetched on its own.
+///
+/// # Errors
+///
+/// Returns an error if a request fails.
+pub fn fetch_properties(
+	cache: &mut Cache,
+	github: &Github,
+	repositories: &[&str],
+	property: &str,
+	token: &str,
+) -> Result<HashMap<String, Option<String>>> {
+	let mut values = repositories
 		.iter()
-		.find(|prop| prop.property_name == "whiskers")
-		.expect("whiskers custom property should exist on all repositories");
+		.filter_map(|&repository| {
+			cache
+				.get::<Option<String>>(&github.key(&property_key(property, repository)))
+				.map(|value| (repository.to_string(), value))
+		})
+		.collect::<HashMap<_, _>>();
+	let missing = |values: &HashMap<String, Option<String>>| {
+		repositories
+			.iter()
+			.filter(|&&repository| !values.contains_key(repository))
+			.map(ToString::to_string)
+			.collect::<Vec<_>>()
+	};
+
+	if !missing(&values).is_empty() && !cache.is_offline() {
+		match fetch_organization_property(github, token, property) {
+			Ok(fetched) => {
+				cache.save_all(fetched.iter().map(|(repository, value)| {
+					(github.key(&property_key(property, repository)), value)
+				}))?;
+				values.extend(
+					fetched
+						.into_iter()
+						.filter(|(repository, _)| repositories.contains(&repository.as_str())),
+				);
+			}
+			Err(err) => {
+				let stale = missing(&values)
+					.into_iter()
+					.map(|repository| {
+						let value = cache.get_stale::<Option<String>>(
+							&github.key(&property_key(property, &repository)),
+						)?;
+						Some((repository, value))
+					})
+					.collect::<Option<Vec<_>>>();
+				let Some(stale) = stale else {
+					return Err(err);
+				};
+				warn!("using expired {property} properties, as fetching them again failed: {err}");
+				values.extend(stale);
+			}
+		}
+	}
+
+	for repository in missing(&values) {
+		let value = fetch_property(cache, github, &repository, property, token.to_string())?;
+		values.insert(repository, value);
+	}
+	Ok(values)
+}
+
+/// Values of a custom property for every repository in the organization, or `None` for repositories without it.
+fn fetch_organization_property(
+	github: &Github,
+	token: &str,
+	property: &str,
+) -> Result<HashMap<String, Option<String>>> {
+	const PER_PAGE: usize = 100;
+	let progress = io::stderr().is_terminal();
+
+	let mut values = HashMap::new();
+	for page in 1.. {
+		if progress {
+			eprint!(
+				"\r\x1b[KFetching {property} properties: page {page} ({} repositories)",
+				values.len()
+			);
+		}
+		let repositories = rest_with_retries(
+			github,
+			&format!(
+				"orgs/{}/properties/values?per_page={PER_PAGE}&page={page}",
+				github.organization
+			),
+			Some(token),
+		)?
+		.json::<Vec<RepositoryProperties>>()?;
+
+		let count = repositories.len();
+		values.extend(repositories.into_iter().map(|repository| {
+			let value = repository
+				.properties
+				.into_iter()
+				.find(|prop| prop.property_name == property)
+				.and_then(|prop| prop.value);
+			(repository.repository_name, value)
+		}));
+
+		if count < PER_PAGE {
+			break;
+		}
+	}
+	if progress {
+		eprintln!(
+			"\r\x1b[KFetched {property} properties of {} repositories",
+			values.len()
+		);
+	}
+
+	Ok(values)
+}
+
+/// Whiskers migration state of a repository, taken from its `whiskers` custom property.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+pub fn fetch_whiskers_status(
+	cache: &mut Cache,
+	github: &Github,
+	repository: &str,
+	token: String,
+) -> Result<String> {
+	fetch_property(cache, github, repository, "whiskers", token)?
+		.ok_or_else(|| missing_property(repository, "whiskers").into())
+}
+
+/// Star count of a repository, taken from the cached list of all repositories if possible.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+pub fn fetch_stargazers(
+	cache: &mut Cache,
+	github: &Github,
+	repository: &str,
+	token: String,
+) -> Result<i64> {
+	let cached = cache
+		.get::<Vec<Option<Repository>>>(&github.key("all-repositories"))
+		.and_then(|repositories| {
+			repositories
+				.into_iter()
+				.flatten()
+				.find(|r| r.name == repository)
+		});
+	if let Some(cached) = cached {
+		return Ok(cached.stargazer_count);
+	}
+
+	cache.get_or(&github.key(&format!("stars-{repository}")), || {
+		Ok(rest(
+			github,
+			&format!("repos/{}/{repository}", github.organization),
+			Some(token),
+		)?
+		.json::<RepositoryResponse>()?
+		.stargazers_count)
+	})
+}
+
+/// Download the tarball of a repository into the cache directory, returning its path. Offline, the previously downloaded tarball is used instead.
+///
+/// # Errors
+///
+/// Returns an error if the download fails, or if offline and no tarball was downloaded before.
+///
+/// # Panics
+///
+/// Panics if the cache path has no parent directory.
+pub fn fetch_tarball(cache: &Cache, repository: &str) -> Result<PathBuf> {
+	let path = cache
+		.path()
+		.with_file_name("tarballs")
+		.join(format!("{}.tar.gz", repository.replace('/', "-")));
+
+	if cache.is_offline() {
+		if !path.exists() {
+			bail!("The {repository} tarball is not cached, so it can't be used while offline (run `purr cache warm` while online first)");
+		}
+		return Ok(path);
+	}
+
+	// Templates live on github.com, whichever API and organization are configured.
+	let bytes = rest(
+		&Github::default(),
+		&format!("repos/{repository}/tarball"),
+		None,
+	)?
+	.bytes()?;
+	fs::create_dir_all(path.parent().expect("tarball path should have a parent"))?;
+	let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+	fs::write(&temporary, bytes)?;
+	fs::rename(&temporary, &path)?;
+	Ok(path)
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tree {
+	pub tree: Vec<TreeEntry>,
+	pub truncated: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeEntry {
+	pub path: String,
+	/// `blob` for files, `tree` for directories.
+	pub r#type: String,
+}
+
+/// All files (and directories) on a branch of a repository, or on its default branch for `HEAD`.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+pub fn fetch_tree(github: &Github, repository: &str, branch: &str, token: &str) -> Result<Tree> {
+	Ok(rest(
+		github,
+		&format!(
+			"{}?recursive=1",
+			encode_path([
+				"repos",
+				&github.organization,
+				repository,
+				"git",
+				"trees",
+				branch
+			])
+		),
+		Some(token.to_string()),
+	)?
+	.json::<Tree>()?)
+}
+
+/// The files (but not directories) on the default branch of a repository.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+pub fn fetch_files(
+	cache: &mut Cache,
+	github: &Github,
+	repository: &str,
+	token: &str,
+) -> Result<Tree> {
+	cache.get_or(&github.key(&format!("files-{repository}")), || {
+		let mut tree = fetch_tree(github, repository, "HEAD", token)?;
+		tree.tree.retain(|entry| entry.r#type == "blob");
+		Ok(tree)
+	})
+}
+
+/// Contents of a file on the default branch of a repository.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+pub fn fetch_file(
+	cache: &mut Cache,
+	github: &Github,
+	repository: &str,
+	path: &str,
+	token: &str,
+) -> Result<String> {
+	cache.get_or(&github.key(&format!("file-{repository}/{path}")), || {
+		let resource = encode_path(
+			["repos", &github.organization, repository, "contents"]
+				.into_iter()
+				.chain(path.split('/')),
+		);
+		let response = request(github, &resource, Some(token.to_string()))
+			.header(reqwest::header::ACCEPT, "application/vnd.github.raw+json")
+			.send()?;
+		Ok(check_response(response, &resource)?.text()?)
+	})
+}
+
+/// Whether any file on a branch of a repository has a `.tera` (Whiskers template) extension. Trees too large for GitHub to list in full are assumed to have one.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+pub fn fetch_has_whiskers_template(
+	cache: &mut Cache,
+	github: &Github,
+	repository: &str,
+	branch: &str,
+	token: &str,
+) -> Result<bool> {
+	cache.get_or(
+		&github.key(&format!("whiskers-template-{repository}")),
+		|| {
+			let tree = fetch_tree(github, repository, branch, token)?;
+
+			let found = tree.tree.iter().any(|entry| {
+				Path::new(&entry.path)
+					.extension()
+					.is_some_and(|e| e == "tera")
+			});
+			if !found && tree.truncated {
+				warn!("the tree of {repository} is too large to check for a Whiskers template");
+				return Ok(true);
+			}
+			Ok(found)
+		},
+	)
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResponse<T> {
+	pub items: Vec<T>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullRequest {
+	pub number: i64,
+	pub title: String,
+	pub html_url: String,
+}
+
+/// Open pull requests by an author across the organization.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+pub fn fetch_open_pull_requests(
+	cache: &mut Cache,
+	github: &Github,
+	author: &str,
+	token: String,
+) -> Result<Vec<PullRequest>> {
+	cache.get_or(
+		&github.key(&format!("pull-requests-{}", author.to_lowercase())),
+		|| {
+			let query = url::form_urlencoded::Serializer::new(String::new())
+				.append_pair(
+					"q",
+					&format!("is:pr is:open org:{} author:{author}", github.organization),
+				)
+				.append_pair("per_page", "100")
+				.finish();
+			Ok(
+				rest(github, &format!("search/issues?{query}"), Some(token))?
+					.json::<SearchResponse<PullRequest>>()?
+					.items,
+			)
+		},
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{BufRead, BufReader, Read, Write};
+	use std::net::TcpListener;
+	use std::thread;
+	use std::time::{Duration, SystemTime};
+
+	use clap::Parser;
+	use reqwest::header::HeaderMap;
+	use reqwest::StatusCode;
+
+	use super::{
+		fetch_all_repositories, fetch_file, fetch_open_pull_requests, fetch_properties,
+		fetch_property, fetch_tree, rest, Github, GithubError,
+	};
+	use crate::cache::Cache;
+	use crate::cli::{Cli, Commands, Query};
+	use crate::token;
+
+	/// Answer requests with the given JSON bodies in order, returning the request line, body and headers of each request.
+	#[allow(clippy::type_complexity)]
+	fn serve(
+		responses: Vec<&'static str>,
+	) -> (
+		String,
+		thread::JoinHandle<Vec<(String, String, Vec<String>)>>,
+	) {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = format!("http://{}", listener.local_addr().unwrap());
+
+		let server = thread::spawn(move || {
+			responses
+				.into_iter()
+				.map(|response| {
+					let (stream, _) = listener.accept().unwrap();
+					let mut reader = BufReader::new(stream);
+					let mut request = String::new();
+					reader.read_line(&mut request).unwrap();
+
+					let mut length = 0;
+					let mut headers = vec![];
+					loop {
+						let mut header = String::new();
+						reader.read_line(&mut header).unwrap();
+						if header.trim().is_empty() {
+							break;
+						}
+						headers.push(header.trim().to_string());
+						if let Some((name, value)) = header.split_once(':') {
+							if name.eq_ignore_ascii_case("content-length") {
+								length = value.trim().parse().unwrap();
+							}
+						}
+					}
+					let mut body = vec![0; length];
+					reader.read_exact(&mut body).unwrap();
+
+					write!(
+						reader.get_mut(),
+						"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{response}",
+						response.len()
+					)
+					.unwrap();
+					(
+						request.trim().to_string(),
+						String::from_utf8(body).unwrap(),
+						headers,
+					)
+				})
+				.collect()
+		});
+		(address, server)
+	}
+
+	#[test]
+	fn describe_errors() {
+		let rate_limited = GithubError::RateLimited {
+			reset: Some(SystemTime::now() + Duration::from_secs(30 * 60 - 1)),
+		};
+		assert_eq!(
+			rate_limited.to_string(),
+			"GitHub API rate limit exceeded, it resets in 30 minutes"
+		);
+		assert_eq!(rate_limited.exit_code(), 4);
+
+		let missing = GithubError::MissingProperty {
+			repository: "nvim".to_string(),
+			property: "whiskers".to_string(),
+		};
+		assert_eq!(
+			missing.to_string(),
+			"repository 'nvim' has no 'whiskers' custom property"
+		);
+
+		let report = color_eyre::eyre::Report::new(missing.clone());
+		assert_eq!(report.downcast_ref::<GithubError>(), Some(&missing));
+	}
+
+	#[test]
+	fn classify_error_responses() {
+		let headers = |pairs: &[(&'static str, &'static str)]| {
+			pairs
+				.iter()
+				.map(|&(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+				.collect::<HeaderMap>()
+		};
+		let classify = |status, pairs: &[(&'static str, &'static str)], body| {
+			GithubError::from_response(status, &headers(pairs), body, "repos/catppuccin/nvim")
+		};
+
+		assert!(matches!(
+			classify(StatusCode::FORBIDDEN, &[("x-ratelimit-remaining", "0")], ""),
+			Some(GithubError::RateLimited { reset: None })
+		));
+		// Secondary rate limits don't use up the primary one.
+		assert!(matches!(
+			classify(
+				StatusCode::FORBIDDEN,
+				&[("x-ratelimit-remaining", "4000"), ("retry-after", "60")],
+				""
+			),
+			Some(GithubError::RateLimited { reset: Some(_) })
+		));
+		assert!(matches!(
+			classify(
+				StatusCode::FORBIDDEN,
+				&[],
+				r#"{"message":"You have exceeded a secondary rate limit."}"#
+			),
+			Some(GithubError::RateLimited { .. })
+		));
+		assert!(matches!(
+			classify(
+				StatusCode::FORBIDDEN,
+				&[],
+				r#"{"message":"Resource not accessible by integration"}"#
+			),
+			Some(GithubError::Unauthorized { .. })
+		));
+	}
+
+	#[test]
+	fn derive_graphql_endpoints() {
+		assert_eq!(Github::default().graphql, "https://api.github.com/graphql");
+		assert_eq!(
+			Github::graphql_endpoint("https://github.example.com/api/v3/"),
+			"https://github.example.com/api/graphql"
+		);
+	}
+
+	#[test]
+	fn query_configured_organization() {
+		let (address, server) = serve(vec![
+			r#"{"data":{"organization":{"repositories":{"nodes":[{"name":"nvim","isArchived":false,"stargazerCount":5,"repositoryTopics":{"nodes":[]},"issues":{"totalCount":0},"pullRequests":{"totalCount":0}}],"pageInfo":{"endCursor":null,"hasNextPage":false,"hasPreviousPage":false}}}}}"#,
+			r#"[{"property_name":"whiskers","value":"true"}]"#,
+		]);
+		let api = format!("{address}/api/v3");
+		let github = Github {
+			graphql: Github::graphql_endpoint(&api),
+			api,
+			organization: "mirror".to_string(),
+		};
 
-	cache.save(&cache_key, property.value.clone())
+		let path = std::env::temp_dir().join("purr-tests/mirror/store.json");
+		let _ = std::fs::remove_file(&path);
+		let mut cache = Cache::new(path, false, 60);
+		let repositories = fetch_all_repositories(&mut cache, &github, "token").unwrap();
+		assert_eq!(repositories[0].as_ref().unwrap().name, "nvim");
+		let value = fetch_property(&mut cache, &github, "nvim", "whiskers", "token".to_string());
+		assert_eq!(value.unwrap().as_deref(), Some("true"));
+
+		let requests = server.join().unwrap();
+		assert_eq!(requests[0].0, "POST /api/graphql HTTP/1.1");
+		assert!(requests[0].1.contains(r#""organization":"mirror""#));
+		assert_eq!(
+			requests[1].0,
+			"GET /api/v3/repos/mirror/nvim/properties/values HTTP/1.1"
+		);
+
+		// The mirror's data is cached separately from the default organization's.
+		assert!(cache.entry("all-repositories").is_none());
+		assert!(cache
+			.entry(&format!("all-repositories@{address}/api/v3/mirror"))
+			.is_some());
+	}
+
+	#[test]
+	fn keep_github_tokens_on_github() {
+		let (address, server) = serve(vec![r#"{"stargazers_count":1}"#]);
+		let github = Github {
+			api: address.clone(),
+			organization: "mirror".to_string(),
+			..Github::default()
+		};
+		assert_eq!(Github::default().host(), "github.com");
+		assert_eq!(github.host(), address.trim_start_matches("http://"));
+
+		// Whatever token is found for github.com, even in GITHUB_TOKEN, none is sent to another host.
+		std::env::set_var("GITHUB_TOKEN", "ghp_github");
+		let cli = Cli::try_parse_from([
+			"purr",
+			"--github-api",
+			&address,
+			"query",
+			"stars",
+			"--for",
+			"nvim",
+		])
+		.unwrap();
+		let Commands::Query {
+			command: Some(Query::Stars(query)),
+			..
+		} = cli.command
+		else {
+			panic!("expected a stars query");
+		};
+		assert_eq!(query.token, None);
+		rest(
+			&github,
+			"repos/mirror/nvim",
+			token::resolve(query.token, &github),
+		)
+		.unwrap();
+		let requests = server.join().unwrap();
+		assert!(!requests[0]
+			.2
+			.iter()
+			.any(|header| header.to_lowercase().starts_with("authorization:")));
+	}
+
+	#[test]
+	fn encode_search_queries() {
+		let (address, server) = serve(vec![r#"{"items":[]}"#]);
+		let github = Github {
+			api: address,
+			organization: "mirror".to_string(),
+			..Github::default()
+		};
+
+		let path = std::env::temp_dir().join("purr-tests/search/store.json");
+		let _ = std::fs::remove_file(&path);
+		let mut cache = Cache::new(path, false, 60);
+		let pull_requests =
+			fetch_open_pull_requests(&mut cache, &github, "a&b c", "token".to_string()).unwrap();
+		assert!(pull_requests.is_empty());
+
+		let requests = server.join().unwrap();
+		assert_eq!(
+			requests[0].0,
+			"GET /search/issues?q=is%3Apr+is%3Aopen+org%3Amirror+author%3Aa%26b+c&per_page=100 HTTP/1.1"
+		);
+	}
+
+	#[test]
+	fn encode_branches_and_paths() {
+		let (address, server) = serve(vec![r#"{"tree":[],"truncated":false}"#, "{}"]);
+		let github = Github {
+			api: address,
+			organization: "mirror".to_string(),
+			..Github::default()
+		};
+
+		let path = std::env::temp_dir().join("purr-tests/paths/store.json");
+		let _ = std::fs::remove_file(&path);
+		let mut cache = Cache::new(path, false, 60);
+		fetch_tree(&github, "nvim", "fix/#1?", "token").unwrap();
+		fetch_file(
+			&mut cache,
+			&github,
+			"nvim",
+			"templates/my theme.tera",
+			"token",
+		)
+		.unwrap();
+
+		let requests = server.join().unwrap();
+		assert_eq!(
+			requests[0].0,
+			"GET /repos/mirror/nvim/git/trees/fix%2F%231%3F?recursive=1 HTTP/1.1"
+		);
+		assert_eq!(
+			requests[1].0,
+			"GET /repos/mirror/nvim/contents/templates/my%20theme.tera HTTP/1.1"
+		);
+	}
+
+	#[test]
+	fn read_cached_properties() {
+		let path = std::env::temp_dir().join("purr-tests/whiskers/store.json");
+		let _ = std::fs::remove_file(&path);
+		let mut cache = Cache::new(path, false, 60).with_offline(true);
+		cache
+			.save_all([
+				("whiskers-nvim".to_string(), Some("true")),
+				("whiskers-bat".to_string(), None),
+			])
+			.unwrap();
+
+		let values = fetch_properties(
+			&mut cache,
+			&Github::default(),
+			&["nvim", "bat"],
+			"whiskers",
+			"token",
+		)
+		.unwrap();
+		assert_eq!(values["nvim"].as_deref(), Some("true"));
+		assert_eq!(values["bat"], None);
+		assert_eq!(
+			super::property_key("whiskers-template", "nvim"),
+			"property-whiskers-template-nvim"
+		);
+		assert!(fetch_properties(
+			&mut cache,
+			&Github::default(),
+			&["kitty"],
+			"whiskers",
+			"token"
+		)
+		.is_err());
+	}
 }
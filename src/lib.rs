@@ -1,6 +1,10 @@
 pub mod cache;
 pub mod cli;
 pub mod cmd;
+pub mod config;
+pub mod format;
 pub mod github;
 pub mod models;
+pub mod sources;
+pub mod token;
 pub mod utils;
@@ -1,27 +1,78 @@
+use std::process::ExitCode;
+
 use clap::Parser;
-use color_eyre::eyre::Result;
+use color_eyre::{eyre::Result, Section};
 use etcetera::{choose_base_strategy, BaseStrategy};
 
-use catppuccin_purr::{cache, cli, cmd};
+use catppuccin_purr::{
+	cache, cli, cmd,
+	config::Config,
+	github::{Github, GithubError},
+	sources::Sources,
+};
 
 static ONE_DAY_IN_SECONDS: u64 = 24 * 60 * 60;
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
 	color_eyre::install()?;
 	pretty_env_logger::formatted_builder()
 		.filter_level(log::LevelFilter::Warn)
 		.init();
 
+	match run() {
+		Ok(code) => Ok(code),
+		Err(report) => {
+			// Known GitHub failures get a hint and their own exit code, so scripts can tell them apart.
+			let Some(err) = report.downcast_ref::<GithubError>() else {
+				return Err(report);
+			};
+			let (code, suggestion) = (err.exit_code(), err.suggestion());
+			eprintln!("Error: {:?}", report.suggestion(suggestion));
+			Ok(ExitCode::from(code))
+		}
+	}
+}
+
+fn run() -> Result<ExitCode> {
 	let args = cli::Cli::parse();
 
+	let config = Config::load(&args.config.unwrap_or_else(Config::default_path))?;
+	let defaults = Sources::default();
+	let sources = Sources {
+		ports: args
+			.ports_source
+			.or(config.sources.ports)
+			.unwrap_or(defaults.ports),
+		userstyles: args
+			.userstyles_source
+			.or(config.sources.userstyles)
+			.unwrap_or(defaults.userstyles),
+		github: {
+			let api = args.github_api.or(config.github.api);
+			Github {
+				graphql: args
+					.github_graphql
+					.or(config.github.graphql)
+					.or_else(|| api.as_deref().map(Github::graphql_endpoint))
+					.unwrap_or(defaults.github.graphql),
+				api: api.unwrap_or(defaults.github.api),
+				organization: args
+					.organization
+					.or(config.github.organization)
+					.unwrap_or(defaults.github.organization),
+			}
+		},
+	};
+
 	let mut cache = cache::Cache::new(
 		choose_base_strategy()
 			.unwrap()
 			.cache_dir()
 			.join("purr/store.json"),
 		args.refresh,
-		ONE_DAY_IN_SECONDS,
-	);
+		args.ttl.unwrap_or(ONE_DAY_IN_SECONDS),
+	)
+	.with_offline(args.offline);
 
 	match args.command {
 		cli::Commands::Query {
@@ -29,24 +80,14 @@ fn main() -> Result<()> {
 			r#for,
 			count,
 			get,
-			_no_userstyles,
-			userstyles,
-			only_userstyles,
-		} => cmd::query::query(
-			&mut cache,
-			command,
-			r#for,
-			count,
-			get,
-			userstyles,
-			only_userstyles,
-		)?,
+			options,
+		} => cmd::query::query(&mut cache, &sources, command, r#for, count, get, *options)?,
 		cli::Commands::Init { command } => match command {
 			cli::Template::Port {
 				name,
 				url,
 				whiskers,
-			} => cmd::init::port(name, url, whiskers)?,
+			} => cmd::init::port(&mut cache, name, url, whiskers)?,
 			cli::Template::Userstyle {
 				name,
 				categories,
@@ -64,8 +105,24 @@ fn main() -> Result<()> {
 				clear_comments,
 			)?,
 		},
-		cli::Commands::Whiskerify { input, output } => cmd::whiskerify::handle(input, output)?,
+		cli::Commands::Whiskerify {
+			input,
+			output,
+			dry_run,
+		} => cmd::whiskerify::handle(&input, output, dry_run)?,
+		cli::Commands::Whiskers { command, format } => {
+			cmd::whiskers::handle(&mut cache, &sources, command, format)?
+		}
+		cli::Commands::Cache { command, format } => {
+			cmd::cache::handle(&mut cache, &sources, command, format)?
+		}
+		cli::Commands::Audit { command, format } => {
+			// Fail when anything is found, for use in CI.
+			if cmd::audit::handle(&mut cache, &sources, command, format)? {
+				return Ok(ExitCode::from(cmd::audit::FINDINGS_EXIT_CODE));
+			}
+		}
 	}
 
-	Ok(())
+	Ok(ExitCode::SUCCESS)
 }
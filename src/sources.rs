@@ -0,0 +1,169 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use color_eyre::eyre::{Context, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use url::Url;
+
+use crate::cache::{Cache, Revalidated};
+use crate::github::Github;
+use crate::models;
+use crate::utils::{fetch_text, fetch_text_revalidated};
+
+pub const DEFAULT_PORTS_SOURCE: &str =
+	"https://github.com/catppuccin/catppuccin/raw/main/resources/ports.yml";
+pub const DEFAULT_USERSTYLES_SOURCE: &str =
+	"https://github.com/catppuccin/userstyles/raw/main/scripts/userstyles.yml";
+
+/// A location that a data file (e.g. `ports.yml`) can be read from, either a remote URL or a local file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Source {
+	Url(Url),
+	Path(PathBuf),
+}
+
+impl Source {
+	/// Read the raw contents of the source, downloading it if it is remote.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the source can't be read or downloaded.
+	pub fn fetch_text(&self) -> Result<String> {
+		match self {
+			Source::Url(url) => fetch_text(url.as_str()),
+			Source::Path(path) => fs::read_to_string(path)
+				.with_context(|| format!("Failed to read '{}'", path.display())),
+		}
+	}
+
+	/// Read and parse the source.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the source can't be read or parsed.
+	pub fn fetch_yaml<T: DeserializeOwned>(&self) -> Result<T> {
+		let raw = self.fetch_text()?;
+		serde_yaml::from_str::<T>(&raw).with_context(|| format!("Failed to parse '{self}'"))
+	}
+
+	/// Fetch and parse the source, caching remote sources under a key that includes the source itself so that entries from different sources never collide. Expired entries are revalidated with a conditional request rather than downloaded again. Local files are always read fresh.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the source can't be read or parsed.
+	pub fn fetch_yaml_cached<T>(&self, cache: &mut Cache, name: &str) -> Result<T>
+	where
+		T: serde::Serialize + DeserializeOwned + Clone,
+	{
+		match self {
+			Source::Url(url) => cache.get_or_revalidate(&format!("{name}@{self}"), |validators| {
+				Ok(match fetch_text_revalidated(url.as_str(), validators)? {
+					Revalidated::Modified(raw, validators) => Revalidated::Modified(
+						serde_yaml::from_str::<T>(&raw)
+							.with_context(|| format!("Failed to parse '{self}'"))?,
+						validators,
+					),
+					Revalidated::NotModified => Revalidated::NotModified,
+				})
+			}),
+			Source::Path(_) => self.fetch_yaml(),
+		}
+	}
+}
+
+impl FromStr for Source {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match Url::parse(s) {
+			Ok(url) if url.scheme() == "file" => url
+				.to_file_path()
+				.map(Source::Path)
+				.map_err(|()| format!("{s} is not a valid file URL")),
+			Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(Source::Url(url)),
+			// Anything else (including Windows drive letters parsed as URL schemes) is treated as a path.
+			_ => Ok(Source::Path(PathBuf::from(s))),
+		}
+	}
+}
+
+impl TryFrom<String> for Source {
+	type Error = String;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		value.parse()
+	}
+}
+
+impl fmt::Display for Source {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Source::Url(url) => write!(f, "{url}"),
+			Source::Path(path) => write!(f, "{}", path.display()),
+		}
+	}
+}
+
+/// The resolved set of data sources used by commands that read ports and userstyles data, and the GitHub organization to query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sources {
+	pub ports: Source,
+	pub userstyles: Source,
+	pub github: Github,
+}
+
+impl Default for Sources {
+	fn default() -> Self {
+		Sources {
+			ports: DEFAULT_PORTS_SOURCE.parse().unwrap(),
+			userstyles: DEFAULT_USERSTYLES_SOURCE.parse().unwrap(),
+			github: Github::default(),
+		}
+	}
+}
+
+impl Sources {
+	/// The ports data, from the cache if possible.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data can't be read or parsed.
+	pub fn ports(&self, cache: &mut Cache) -> Result<models::ports::Root> {
+		self.ports.fetch_yaml_cached(cache, "ports-yml")
+	}
+
+	/// The userstyles data, from the cache if possible.
+	///
+	/// # Errors
+	///
+	/// Returns an error if the data can't be read or parsed.
+	pub fn userstyles(&self, cache: &mut Cache) -> Result<models::userstyles::Root> {
+		self.userstyles.fetch_yaml_cached(cache, "userstyles-yml")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::Source;
+
+	#[test]
+	fn parse_sources() {
+		assert!(matches!(
+			"https://example.com/ports.yml".parse::<Source>(),
+			Ok(Source::Url(_))
+		));
+		assert_eq!(
+			"file:///tmp/ports.yml".parse::<Source>(),
+			Ok(Source::Path(PathBuf::from("/tmp/ports.yml")))
+		);
+		assert_eq!(
+			"resources/ports.yml".parse::<Source>(),
+			Ok(Source::Path(PathBuf::from("resources/ports.yml")))
+		);
+	}
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+use color_eyre::eyre::{eyre, Result};
+use etcetera::{choose_base_strategy, BaseStrategy};
+use log::debug;
+use serde::Deserialize;
+
+use crate::cache::Cache;
+use crate::github::Github;
+
+/// The host of the public GitHub, whose tokens are also found in `GITHUB_TOKEN` and `GH_TOKEN`.
+pub const GITHUB_HOST: &str = "github.com";
+
+#[derive(Debug, Deserialize)]
+struct GhHost {
+	oauth_token: Option<String>,
+}
+
+/// The token given with `--token`, or else one discovered for the host of the API with [`discover`].
+#[must_use]
+pub fn resolve(token: Option<String>, github: &Github) -> Option<String> {
+	token
+		.filter(|token| !token.is_empty())
+		.or_else(|| discover(&github.host()))
+}
+
+/// Like [`resolve`], but failing with instructions if no token can be found. Offline, nothing is fetched and cached data needs no token, so a missing token is left empty instead.
+///
+/// # Errors
+///
+/// Returns an error if no token can be found while online.
+pub fn require(token: Option<String>, github: &Github, cache: &Cache) -> Result<String> {
+	let host = github.host();
+	let token = resolve(token, github);
+	if cache.is_offline() {
+		return Ok(token.unwrap_or_default());
+	}
+	token.ok_or_else(|| {
+		if host == GITHUB_HOST {
+			eyre!("no GitHub token found, pass one with --token, set GITHUB_TOKEN or GH_TOKEN, or log in with `gh auth login`")
+		} else {
+			eyre!("no GitHub token found for {host}, pass one with --token, set GH_ENTERPRISE_TOKEN, or log in with `gh auth login --hostname {host}`")
+		}
+	})
+}
+
+/// Find a token for a GitHub host without asking for one. For github.com, `GITHUB_TOKEN`, `GH_TOKEN`, the GitHub CLI's hosts file and then `git credential fill` are tried, in that order. Tokens for github.com are never used for other hosts, so for those (like GitHub Enterprise) `GH_ENTERPRISE_TOKEN` and `GITHUB_ENTERPRISE_TOKEN` are tried instead of the former two, and the hosts file and git credentials are asked for that host. The result is remembered for the rest of the run.
+pub fn discover(host: &str) -> Option<String> {
+	static TOKENS: OnceLock<Mutex<HashMap<String, Option<String>>>> = OnceLock::new();
+
+	let mut tokens = TOKENS
+		.get_or_init(Mutex::default)
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner);
+	tokens
+		.entry(host.to_string())
+		.or_insert_with(|| {
+			let token = from_env(host)
+				.map(|token| (token, "the environment"))
+				.or_else(|| from_gh(host).map(|token| (token, "the GitHub CLI")))
+				.or_else(|| from_git_credential(host).map(|token| (token, "git credential fill")));
+			token.map(|(token, source)| {
+				debug!("using the GitHub token for {host} from {source}");
+				token
+			})
+		})
+		.clone()
+}
+
+fn from_env(host: &str) -> Option<String> {
+	let names = if host == GITHUB_HOST {
+		["GITHUB_TOKEN", "GH_TOKEN"]
+	} else {
+		["GH_ENTERPRISE_TOKEN", "GITHUB_ENTERPRISE_TOKEN"]
+	};
+	names
+		.into_iter()
+		.find_map(|name| env::var(name).ok().filter(|token| !token.is_empty()))
+}
+
+/// The GitHub CLI's config directory, see `gh help environment`.
+fn gh_config_dir() -> Option<PathBuf> {
+	if let Some(dir) = env::var_os("GH_CONFIG_DIR") {
+		return Some(dir.into());
+	}
+	let dir = if cfg!(windows) { "GitHub CLI" } else { "gh" };
+	Some(choose_base_strategy().ok()?.config_dir().join(dir))
+}
+
+fn from_gh(host: &str) -> Option<String> {
+	let contents = fs::read_to_string(gh_config_dir()?.join("hosts.yml")).ok()?;
+	parse_gh_hosts(&contents, host)
+}
+
+/// Newer versions of the GitHub CLI keep the token in the system keyring instead, in which case the hosts file has none.
+fn parse_gh_hosts(contents: &str, host: &str) -> Option<String> {
+	serde_yaml::from_str::<HashMap<String, GhHost>>(contents)
+		.ok()?
+		.remove(host)?
+		.oauth_token
+		.filter(|token| !token.is_empty())
+}
+
+fn from_git_credential(host: &str) -> Option<String> {
+	// Credential helpers must not prompt, purr only uses credentials that are already stored.
+	let mut child = Command::new("git")
+		.args(["credential", "fill"])
+		.env("GIT_TERMINAL_PROMPT", "0")
+		.env("GCM_INTERACTIVE", "never")
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::null())
+		.spawn()
+		.ok()?;
+	child
+		.stdin
+		.take()?
+		.write_all(format!("protocol=https\nhost={host}\n\n").as_bytes())
+		.ok()?;
+
+	let output = child.wait_with_output().ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	parse_credential(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_credential(output: &str) -> Option<String> {
+	output
+		.lines()
+		.find_map(|line| line.strip_prefix("password="))
+		.filter(|password| !password.is_empty())
+		.map(ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_credential, parse_gh_hosts, require};
+	use crate::cache::Cache;
+	use crate::github::Github;
+
+	#[test]
+	fn only_require_tokens_online() {
+		let github = Github {
+			api: "http://127.0.0.1:9".to_string(),
+			..Github::default()
+		};
+		let path = std::env::temp_dir().join("purr-tests/token/store.json");
+
+		let cache = Cache::new(path, false, 60);
+		assert!(require(None, &github, &cache).is_err());
+		let cache = cache.with_offline(true);
+		assert_eq!(require(None, &github, &cache).unwrap(), "");
+		assert_eq!(
+			require(Some("ghp_token".to_string()), &github, &cache).unwrap(),
+			"ghp_token"
+		);
+	}
+
+	#[test]
+	fn read_stored_tokens() {
+		let hosts = "github.com:\n    users:\n        uncenter:\n            oauth_token: gho_users\n    git_protocol: https\n    oauth_token: gho_host\n    user: uncenter\nghe.example.com:\n    oauth_token: gho_enterprise\n";
+		assert_eq!(
+			parse_gh_hosts(hosts, "github.com").as_deref(),
+			Some("gho_host")
+		);
+		assert_eq!(
+			parse_gh_hosts(hosts, "ghe.example.com").as_deref(),
+			Some("gho_enterprise")
+		);
+		assert_eq!(
+			parse_gh_hosts(
+				"github.com:\n    user: uncenter\n    git_protocol: https\n",
+				"github.com"
+			),
+			None
+		);
+
+		assert_eq!(
+			parse_credential(
+				"protocol=https\nhost=github.com\nusername=uncenter\npassword=ghp_token\n"
+			)
+			.as_deref(),
+			Some("ghp_token")
+		);
+		assert_eq!(parse_credential("protocol=https\nhost=github.com\n"), None);
+	}
+}
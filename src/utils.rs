@@ -1,5 +1,11 @@
-use color_eyre::eyre::Result;
-use serde::de::DeserializeOwned;
+use std::cmp::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use color_eyre::{eyre::Result, owo_colors::OwoColorize};
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+
+use crate::cache::{Revalidated, Validators};
 
 pub fn fetch_text(url: &str) -> Result<String> {
 	let response = reqwest::blocking::get(url)?;
@@ -7,7 +13,95 @@ pub fn fetch_text(url: &str) -> Result<String> {
 	Ok(text)
 }
 
-pub fn fetch_yaml<T: DeserializeOwned>(url: &str) -> Result<T> {
-	let raw = fetch_text(url)?;
-	return Ok(serde_yaml::from_str::<T>(&raw)?);
+/// Download text unless it is unchanged since the response the validators came from.
+///
+/// # Errors
+///
+/// Returns an error if the request fails.
+pub fn fetch_text_revalidated(url: &str, validators: &Validators) -> Result<Revalidated<String>> {
+	let mut request = reqwest::blocking::Client::new().get(url);
+	if let Some(etag) = &validators.etag {
+		request = request.header(IF_NONE_MATCH, etag);
+	}
+	if let Some(last_modified) = &validators.last_modified {
+		request = request.header(IF_MODIFIED_SINCE, last_modified);
+	}
+
+	let response = request.send()?;
+	if response.status() == StatusCode::NOT_MODIFIED {
+		return Ok(Revalidated::NotModified);
+	}
+
+	let response = response.error_for_status()?;
+	let header = |headers: &HeaderMap, name| {
+		headers
+			.get(name)
+			.and_then(|value| value.to_str().ok())
+			.map(ToString::to_string)
+	};
+	let validators = Validators {
+		etag: header(response.headers(), ETAG),
+		last_modified: header(response.headers(), LAST_MODIFIED),
+	};
+	Ok(Revalidated::Modified(response.text()?, validators))
+}
+
+/// Format a time as an ISO 8601 timestamp in UTC, e.g. `2024-01-01T00:00:00Z`. Timestamps in this format sort chronologically as strings.
+#[must_use]
+pub fn format_timestamp(time: SystemTime) -> String {
+	let seconds = time
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_secs();
+	let (days, time) = (seconds / 86400, seconds % 86400);
+
+	// Days since the epoch to a civil date, see https://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+	let z = days + 719_468;
+	let era = z / 146_097;
+	let doe = z % 146_097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + u64::from(month <= 2);
+
+	format!(
+		"{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+		time / 3600,
+		time / 60 % 60,
+		time % 60
+	)
+}
+
+/// Rank candidates by similarity to the input (case-insensitively), best first, dropping unlikely matches.
+pub fn suggest<'a>(
+	input: &str,
+	candidates: impl IntoIterator<Item = &'a str>,
+) -> Vec<(&'a str, f64)> {
+	let input = input.to_lowercase();
+	let mut distances = candidates
+		.into_iter()
+		.map(|candidate| (candidate, strsim::jaro(&input, &candidate.to_lowercase())))
+		.filter(|(_, confidence)| *confidence > 0.7)
+		.collect::<Vec<_>>();
+
+	distances.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+	distances.dedup_by(|a, b| a.0 == b.0);
+	distances
+}
+
+/// Format suggestions as a "did you mean" hint to append to an error message, or an empty string if there are none.
+#[must_use]
+pub fn did_you_mean(suggestions: &[&str]) -> String {
+	let quoted = suggestions
+		.iter()
+		.map(|s| format!("'{}'", s.green()))
+		.collect::<Vec<_>>();
+
+	match quoted.as_slice() {
+		[] => String::new(),
+		[only] => format!(". Did you mean {only}?"),
+		[rest @ .., last] => format!(". Did you mean {} or {last}?", rest.join(", ")),
+	}
 }